@@ -3,11 +3,21 @@ use crate::editor::*;
 use crate::game::*;
 use crate::kgui::EventAggregator;
 use crate::kgui::FrameInputState;
-use crate::level::Level;
+use crate::level::LevelMetadata;
 use crate::renderer::*;
 use crate::rendererUV::*;
 use crate::kmath::*;
-use glutin::event::{Event, WindowEvent};
+use crate::progress::*;
+use crate::recovery::*;
+use crate::bindings::*;
+use crate::raster::*;
+use crate::replay::*;
+use crate::kimg::*;
+use crate::manifest::*;
+use crate::kgui::KEvent;
+use glutin::event::{Event, WindowEvent, VirtualKeyCode};
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 pub enum SceneOutcome {
     Push(Box<dyn Scene>),
@@ -17,12 +27,64 @@ pub enum SceneOutcome {
 
 pub enum SceneSignal {
     JustPop,
-    LevelChoice(Level),
+    LevelChoice(LevelMetadata),
+    RestoreEditor(Box<EditorRecovery>),
 }
 
 pub trait Scene {
     fn handle_signal(&mut self, signal: SceneSignal) -> SceneOutcome;
-    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, TriangleBuffer, Option<TriangleBufferUV>);
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>);
+}
+
+// Some of a frame and the camera both renderers present it through. Most things are drawn straight onto the
+// screen, the board gets a layer of its own so zooming, panning and shaking it only change the view.
+// Layers go on in order and share the depth buffer. clip is the part of the window it can show on,
+// 0..1 across and down like Camera::to_viewport
+pub struct Layer {
+    pub camera: Camera,
+    pub clip: Option<Rect>,
+    pub tris: TriangleBuffer,
+    pub tri_uvs: Option<TriangleBufferUV>,
+}
+
+impl Layer {
+    pub fn screen(screen_rect: Rect, tris: TriangleBuffer, tri_uvs: Option<TriangleBufferUV>) -> Layer {
+        Layer { camera: Camera::new(screen_rect), clip: None, tris, tri_uvs }
+    }
+}
+
+// The scenes without the window, so they can be driven headless too
+pub struct SceneStack {
+    pub scenes: Vec<Box<dyn Scene>>,
+}
+
+impl SceneStack {
+    pub fn new(root: Box<dyn Scene>) -> SceneStack {
+        SceneStack { scenes: vec![root] }
+    }
+
+    pub fn handle_scene_outcome(&mut self, so: SceneOutcome) {
+        match so {
+            SceneOutcome::Push(scene) => {
+                self.scenes.push(scene);
+            },
+            SceneOutcome::Pop(signal) => {
+                self.scenes.pop();
+                let stack_idx = self.scenes.len() - 1;
+                let so = self.scenes[stack_idx].handle_signal(signal);
+                self.handle_scene_outcome(so);
+            },
+            SceneOutcome::None => {},
+        }
+    }
+
+    // runs the top scene, whatever it asks for happens after its drawn
+    pub fn frame(&mut self, inputs: FrameInputState) -> Vec<Layer> {
+        let stack_idx = self.scenes.len() - 1;
+        let (so, layers) = self.scenes[stack_idx].frame(inputs);
+        self.handle_scene_outcome(so);
+        layers
+    }
 }
 
 pub struct Application {
@@ -35,12 +97,15 @@ pub struct Application {
 
     pub xres: f32,
     pub yres: f32,
+
+    last_frame: Instant,
+    recorder: Option<Recorder>,
     
-    scene_stack: Vec<Box<dyn Scene>>,
+    scene_stack: SceneStack,
 }
 
 impl Application {
-    pub fn new(event_loop: &glutin::event_loop::EventLoop<()>) -> Application {
+    pub fn new(event_loop: &glutin::event_loop::EventLoop<()>, record_path: Option<&str>) -> Application {
         let default_xres = 1600.0;
         let default_yres = 900.0;
 
@@ -50,10 +115,30 @@ impl Application {
         let uv_shader = make_shader(&gl, "src/uv.vert", "src/uv.frag");
 
         let renderer = Renderer::new(&gl, basic_shader);
-        let rendererUV = RendererUV::new(&gl, uv_shader, "src/atlas.png");
+        let rendererUV = RendererUV::new(&gl, uv_shader, ATLAS_PATH);
 
-        let mut scene_stack: Vec<Box<dyn Scene>> = Vec::new();
-        scene_stack.push(Box::new(Editor::new()));
+        let progress = Progress::load_shared();
+        let bindings = Bindings::load_shared();
+        let recovery = EditorRecovery::load();
+
+        // replay.rs builds the same stack from this
+        let recorder = record_path.and_then(|path| {
+            let header = ReplayHeader {
+                version: REPLAY_VERSION,
+                xres: default_xres,
+                yres: default_yres,
+                progress: progress.borrow().clone(),
+                recovery: recovery.clone(),
+                bindings: (*bindings).clone(),
+            };
+            Recorder::new(path, &header).map_err(|e| println!("couldn't record to {}: {}", path, e)).ok()
+        });
+
+        let mut scene_stack = SceneStack::new(Box::new(Editor::new(progress, bindings)));
+        if let Some(recovery) = recovery {
+            println!("found unsaved editor state in {}", RECOVERY_PATH);
+            scene_stack.scenes.push(Box::new(RecoveryPrompt::new(recovery)));
+        }
 
         Application {
             gl,
@@ -65,22 +150,10 @@ impl Application {
             xres: default_xres,
             yres: default_yres,
 
-            scene_stack,
-        }
-    }
+            last_frame: Instant::now(),
+            recorder,
 
-    pub fn handle_scene_outcome(&mut self, so: SceneOutcome) {
-        match so {
-            SceneOutcome::Push(scene) => {
-                self.scene_stack.push(scene);
-            },
-            SceneOutcome::Pop(signal) => {
-                self.scene_stack.pop();
-                let stack_idx = self.scene_stack.len() - 1;
-                let so = self.scene_stack[stack_idx].handle_signal(signal);
-                self.handle_scene_outcome(so);
-            },
-            SceneOutcome::None => {},
+            scene_stack,
         }
     }
 
@@ -99,20 +172,58 @@ impl Application {
             _ => {},
         }
 
-        if let Some(inputs) = self.event_aggregator.handle_event(event) {
-            let stack_idx = self.scene_stack.len()-1; 
-            
+        if let Some(mut inputs) = self.event_aggregator.handle_event(event) {
+            let now = Instant::now();
+            inputs.dt = now.duration_since(self.last_frame).as_secs_f32();
+            self.last_frame = now;
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.record(&inputs);
+            }
+
+            let screenshot = inputs.events.iter().any(|e| match e {KEvent::Keyboard(VirtualKeyCode::F12, true) => true, _ => false});
             unsafe { self.gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT); } 
 
-            let (so, tris, op_triuvs) = self.scene_stack[stack_idx].frame(inputs);
-            self.renderer.present(&self.gl, tris);
-            if let Some(tri_uvs) = op_triuvs {
-                self.rendererUV.present(&self.gl, tri_uvs);
+            let layers = self.scene_stack.frame(inputs);
+            if screenshot {
+                self.screenshot(&layers);
+            }
+            for layer in layers {
+                self.present(layer);
             }
             self.window.swap_buffers().unwrap();
+        }
+    }
+
+    // the clip is cut out with the scissor test, GL counts its pixels up from the bottom
+    fn present(&mut self, layer: Layer) {
+        if let Some(clip) = layer.clip {
+            unsafe {
+                self.gl.enable(glow::SCISSOR_TEST);
+                self.gl.scissor(
+                    (clip.x * self.xres).round() as i32,
+                    ((1.0 - clip.bot()) * self.yres).round() as i32,
+                    (clip.w * self.xres).round() as i32,
+                    (clip.h * self.yres).round() as i32,
+                );
+            }
+        }
+        self.renderer.present(&self.gl, layer.tris, &layer.camera);
+        if let Some(tri_uvs) = layer.tri_uvs {
+            self.rendererUV.present(&self.gl, tri_uvs, &layer.camera);
+        }
+        unsafe { self.gl.disable(glow::SCISSOR_TEST); }
+    }
 
-            self.handle_scene_outcome(so);
+    // drawn again on the CPU rather than read back from GL
+    fn screenshot(&self, layers: &[Layer]) {
+        let mut rasterizer = Rasterizer::new(self.xres as usize, self.yres as usize, ImageBufferA::new_from_file(ATLAS_PATH));
+        for layer in layers {
+            rasterizer.draw(layer);
         }
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let path = format!("screenshot-{}.png", secs);
+        rasterizer.image.dump_to_file(&path);
+        println!("saved {}", path);
     }
 
     pub fn destroy(&mut self) {
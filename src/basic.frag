@@ -1,7 +1,7 @@
-in vec3 vert_colour;
+in vec4 vert_colour;
 
 out vec4 frag_colour;
 
 void main() {
-    frag_colour = vec4(vert_colour, 1.0);
+    frag_colour = vert_colour;
 }
\ No newline at end of file
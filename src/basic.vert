@@ -1,27 +1,10 @@
 layout (location = 0) in vec3 in_pos;
-layout (location = 1) in vec3 in_colour;
+layout (location = 1) in vec4 in_colour;
 
-// uniform mat4 projection;
-const mat4 projection = mat4(
-    2, 0, 0, 0,
-    0, -2, 0, 0,
-    0, 0, -0.001, 0,
-    -1, 1, 1, 1
-);
-// mat4 projection = [
-//     2, 0, 0, -1,
-//     0, -2, 0, 1,
-//     0, 0, -0.001, 1,
-//     0, 0, 0, 1,
-// ];
-// const mat4 projection = mat4(
-//     1, 0, 0, 0,
-//     0, 1, 0, 0,
-//     0, 0, 1, 0,
-//     0, 0, 0, 1
-// );
+// from Camera::projection, screen units to clip space
+uniform mat4 projection;
 
-out vec3 vert_colour;
+out vec4 vert_colour;
 
 void main() {
     vert_colour = in_colour;
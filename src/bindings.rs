@@ -0,0 +1,279 @@
+use crate::application::*;
+use crate::editor::*;
+use crate::game::*;
+use crate::kgui::*;
+use crate::kmath::*;
+use crate::level_menu::*;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use glutin::event::VirtualKeyCode;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+pub const BINDINGS_PATH: &str = "bindings.json";
+
+// loaded once by Application and handed down like progress is
+pub type SharedBindings = Rc<Bindings>;
+
+// A key and the modifiers held with it, written like Ctrl+Shift+Z. Key names are winits VirtualKeyCode names.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct KeyChord {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub key: VirtualKeyCode,
+}
+
+impl KeyChord {
+    pub const fn new(key: VirtualKeyCode) -> KeyChord {
+        KeyChord { ctrl: false, shift: false, alt: false, key }
+    }
+
+    pub const fn ctrl(key: VirtualKeyCode) -> KeyChord {
+        KeyChord { ctrl: true, shift: false, alt: false, key }
+    }
+
+    pub const fn shift(key: VirtualKeyCode) -> KeyChord {
+        KeyChord { ctrl: false, shift: true, alt: false, key }
+    }
+
+    pub fn parse(s: &str) -> Result<KeyChord, String> {
+        let mut parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        let key_name = parts.pop().unwrap_or("");
+        let key: VirtualKeyCode = serde_json::from_value(serde_json::Value::from(key_name))
+            .map_err(|_| format!("{} is not a key", key_name))?;
+        let mut chord = KeyChord::new(key);
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" => chord.alt = true,
+                _ => return Err(format!("{} is not a modifier, use ctrl, shift or alt", modifier)),
+            }
+        }
+        Ok(chord)
+    }
+
+    // modifiers have to match exactly, so Z and Ctrl+Z can do different things
+    pub fn pressed(&self, key: VirtualKeyCode, modifiers: Modifiers) -> bool {
+        key == self.key && self.ctrl == modifiers.ctrl && self.shift == modifiers.shift && self.alt == modifiers.alt
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl { write!(f, "Ctrl+")?; }
+        if self.shift { write!(f, "Shift+")?; }
+        if self.alt { write!(f, "Alt+")?; }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+impl Serialize for KeyChord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<KeyChord, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        KeyChord::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+// What each key does in each kind of scene.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Bindings {
+    #[serde(default)]
+    pub editor: BTreeMap<KeyChord, EditorCommand>,
+    #[serde(default)]
+    pub game: BTreeMap<KeyChord, GameAction>,
+    // the level menu and the pack menu
+    #[serde(default)]
+    pub menu: BTreeMap<KeyChord, MenuCommand>,
+}
+
+// How bindings.json is read. A section thats in the file replaces that whole section of the defaults,
+// so keys can be moved or unbound. Sections left out keep the defaults.
+#[derive(Deserialize)]
+struct BindingsFile {
+    editor: Option<BTreeMap<KeyChord, EditorCommand>>,
+    game: Option<BTreeMap<KeyChord, GameAction>>,
+    menu: Option<BTreeMap<KeyChord, MenuCommand>>,
+}
+
+impl Bindings {
+    pub fn defaults() -> Bindings {
+        use VirtualKeyCode::*;
+        let editor = [
+            (KeyChord::new(Q), EditorCommand::RotateLeft),
+            (KeyChord::new(E), EditorCommand::RotateRight),
+            (KeyChord::new(Space), EditorCommand::PlayLevel),
+            (KeyChord::new(O), EditorCommand::LoadLevel),
+            (KeyChord::new(S), EditorCommand::SaveLevel),
+            (KeyChord::ctrl(S), EditorCommand::SaveLevel),
+            (KeyChord::new(T), EditorCommand::SaveLevelText),
+            (KeyChord::new(A), EditorCommand::SaveLevelAs),
+            (KeyChord::new(Z), EditorCommand::Undo),
+            (KeyChord::ctrl(Z), EditorCommand::Undo),
+            (KeyChord::new(Y), EditorCommand::Redo),
+            (KeyChord::ctrl(Y), EditorCommand::Redo),
+            (KeyChord::new(X), EditorCommand::ExportCode),
+            (KeyChord::new(I), EditorCommand::ImportCode),
+            (KeyChord::new(P), EditorCommand::ExportThumbnail),
+            (KeyChord::new(F), EditorCommand::FillSelection),
+            (KeyChord::new(Delete), EditorCommand::ClearSelection),
+            (KeyChord::new(F1), EditorCommand::Help),
+        ];
+        let game = [
+            (KeyChord::new(Q), GameAction::RotateLeft),
+            (KeyChord::new(E), GameAction::RotateRight),
+            (KeyChord::new(H), GameAction::Hint),
+            (KeyChord::new(Escape), GameAction::Quit),
+            (KeyChord::new(F1), GameAction::Help),
+            (KeyChord::new(W), GameAction::MoveCursor((0, -1))),
+            (KeyChord::new(S), GameAction::MoveCursor((0, 1))),
+            (KeyChord::new(A), GameAction::MoveCursor((-1, 0))),
+            (KeyChord::new(D), GameAction::MoveCursor((1, 0))),
+            (KeyChord::new(Up), GameAction::MoveCursor((0, -1))),
+            (KeyChord::new(Down), GameAction::MoveCursor((0, 1))),
+            (KeyChord::new(Left), GameAction::MoveCursor((-1, 0))),
+            (KeyChord::new(Right), GameAction::MoveCursor((1, 0))),
+            (KeyChord::new(Key1), GameAction::PickPalette(0)),
+            (KeyChord::new(Key2), GameAction::PickPalette(1)),
+            (KeyChord::new(Key3), GameAction::PickPalette(2)),
+            (KeyChord::new(Key4), GameAction::PickPalette(3)),
+            (KeyChord::new(Key5), GameAction::PickPalette(4)),
+            (KeyChord::new(Key6), GameAction::PickPalette(5)),
+            (KeyChord::new(Key7), GameAction::PickPalette(6)),
+            (KeyChord::new(Key8), GameAction::PickPalette(7)),
+            (KeyChord::new(Key9), GameAction::PickPalette(8)),
+            (KeyChord::new(Tab), GameAction::NextPalette),
+            (KeyChord::shift(Tab), GameAction::PrevPalette),
+            (KeyChord::new(Return), GameAction::Place),
+            (KeyChord::new(Back), GameAction::Clear),
+            (KeyChord::new(Delete), GameAction::Clear),
+        ];
+        let menu = [
+            (KeyChord::new(W), MenuCommand::Move((0, -1))),
+            (KeyChord::new(S), MenuCommand::Move((0, 1))),
+            (KeyChord::new(A), MenuCommand::Move((-1, 0))),
+            (KeyChord::new(D), MenuCommand::Move((1, 0))),
+            (KeyChord::new(Up), MenuCommand::Move((0, -1))),
+            (KeyChord::new(Down), MenuCommand::Move((0, 1))),
+            (KeyChord::new(Left), MenuCommand::Move((-1, 0))),
+            (KeyChord::new(Right), MenuCommand::Move((1, 0))),
+            (KeyChord::new(PageUp), MenuCommand::Page(-1)),
+            (KeyChord::new(PageDown), MenuCommand::Page(1)),
+            (KeyChord::new(Return), MenuCommand::Select),
+            (KeyChord::new(Space), MenuCommand::Play),
+            (KeyChord::new(Escape), MenuCommand::Quit),
+            (KeyChord::new(F1), MenuCommand::Help),
+        ];
+        Bindings {
+            editor: editor.into_iter().collect(),
+            game: game.into_iter().collect(),
+            menu: menu.into_iter().collect(),
+        }
+    }
+
+    // The defaults are written out the first time so theres something to edit.
+    // A file that doesn't parse is reported and ignored rather than half applied.
+    pub fn load() -> Bindings {
+        let mut bindings = Bindings::defaults();
+        match std::fs::read_to_string(BINDINGS_PATH) {
+            Ok(contents) => match serde_json::from_str::<BindingsFile>(&contents) {
+                Ok(user) => {
+                    if let Some(editor) = user.editor {
+                        bindings.editor = editor;
+                    }
+                    if let Some(game) = user.game {
+                        bindings.game = game;
+                    }
+                    if let Some(menu) = user.menu {
+                        bindings.menu = menu;
+                    }
+                },
+                Err(e) => println!("couldn't parse {}: {}, using the default bindings", BINDINGS_PATH, e),
+            },
+            Err(_) => {
+                let str = serde_json::to_string_pretty(&bindings).unwrap();
+                match std::fs::write(BINDINGS_PATH, str) {
+                    Ok(()) => println!("wrote the default bindings to {}", BINDINGS_PATH),
+                    Err(e) => println!("couldn't write {}: {}", BINDINGS_PATH, e),
+                }
+            },
+        }
+        bindings
+    }
+
+    pub fn load_shared() -> SharedBindings {
+        Rc::new(Bindings::load())
+    }
+}
+
+// what this frames key presses mean, in the order they were pressed
+pub fn actions<A: Copy>(bindings: &BTreeMap<KeyChord, A>, inputs: &FrameInputState) -> Vec<A> {
+    inputs.events.iter().filter_map(|e| match e {
+        KEvent::Keyboard(key, true) => bindings.iter().find(|(chord, _)| chord.pressed(*key, inputs.modifiers)).map(|(_, a)| *a),
+        _ => None,
+    }).collect()
+}
+
+// first key for an action, for tooltips
+pub fn chord_for<A: PartialEq>(bindings: &BTreeMap<KeyChord, A>, action: A) -> Option<KeyChord> {
+    bindings.iter().find(|(_, a)| **a == action).map(|(chord, _)| *chord)
+}
+
+// the action the way its written in the file, Move((0, -1)) comes out as Move 0 -1
+fn action_name<A: Serialize>(action: &A) -> String {
+    match serde_json::to_value(action).unwrap() {
+        serde_json::Value::String(s) => s,
+        value => value.to_string().chars().map(|c| if "{}[]\":,".contains(c) { ' ' } else { c }).collect::<String>()
+            .split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+// Lists whats bound where it was opened from. Escape, F1 or the close button goes back
+pub struct HelpScreen {
+    title: String,
+    lines: Vec<String>,
+    scroll: usize,
+}
+
+impl HelpScreen {
+    pub fn new<A: Serialize>(title: &str, bindings: &BTreeMap<KeyChord, A>) -> HelpScreen {
+        let lines = bindings.iter().map(|(chord, action)| format!("{:<16}{}", chord.to_string(), action_name(action))).collect();
+        HelpScreen { title: title.to_string(), lines, scroll: 0 }
+    }
+}
+
+impl Scene for HelpScreen {
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>) {
+        let mut gui = Gui::new(&inputs);
+        let panel = inputs.screen_rect.fit_center_square().dilate(-0.05);
+        let title_rect = panel.child(0.0, 0.0, 1.0, 0.1);
+        let list_rect = panel.child(0.0, 0.12, 1.0, 0.8);
+        let footer_rect = panel.child(0.0, 0.94, 0.75, 0.06);
+        let close_rect = panel.child(0.8, 0.94, 0.2, 0.06);
+
+        gui.buf_uv.draw_text(title_rect, &self.title, Vec3::new(1.0, 1.0, 1.0), 2.0);
+        gui.scroll_list(list_rect, &self.lines, 0.04, &mut self.scroll, None);
+        let footer = format!("keys are set in {}", BINDINGS_PATH);
+        gui.buf_uv.draw_text(footer_rect, &footer, Vec3::new(0.6, 0.6, 0.6), 2.0);
+
+        let close = gui.button(close_rect, ButtonAppearance::Text("close"))
+            || inputs.key_pressed(VirtualKeyCode::Escape) || inputs.key_pressed(VirtualKeyCode::F1);
+        let outcome = if close { SceneOutcome::Pop(SceneSignal::JustPop) } else { SceneOutcome::None };
+        let (buf, buf_uv) = gui.finish();
+        (outcome, vec![Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])
+    }
+
+    fn handle_signal(&mut self, _signal: SceneSignal) -> SceneOutcome {
+        SceneOutcome::None
+    }
+}
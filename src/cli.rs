@@ -0,0 +1,226 @@
+use crate::kimg::*;
+use crate::level::*;
+use crate::level_text::*;
+use crate::manifest::*;
+use crate::pack::*;
+use crate::raster::*;
+
+use std::path::Path;
+
+// Level tooling for scripts and the build machines, none of this opens a window.
+// Every command prints what it did and the exit code says whether it went ok.
+
+const USAGE: &str = "usage:
+  solve <file> [-o out] [--limit N]                       solve a level, prints the board or writes it to out
+  validate <dir> [--limit N]                              check every level loads, is solvable and isnt a duplicate
+  generate --w W --h H --colours N --seed S [-o out]      make a level, prints it or writes it to out
+  render <file> -o out.png [--size px]                    draw a level to a png
+  stats <dir> [--limit N]                                 size, colours, solution count and difficulty of every level
+--limit is how many tiles the solver tries before giving up on a level, default 10000000";
+
+// past this many a level counts as wide open, counting them all takes forever on big boards
+const SOLUTION_LIMIT: usize = 100;
+// tiles tried before the solver gives up, big open boards would otherwise never finish
+const DEFAULT_TRY_LIMIT: u64 = 10_000_000;
+const DEFAULT_RENDER_SIZE: usize = 512;
+// baked in so render works from any directory, not just the repo
+const ATLAS_PNG: &[u8] = include_bytes!("atlas.png");
+
+// None when args[0] isnt a command, then the game runs as normal
+pub fn run_command(args: &[String]) -> Option<i32> {
+    let result = match args.get(0).map(|a| a.as_str()) {
+        Some("solve") => solve(&args[1..]),
+        Some("validate") => validate(&args[1..]),
+        Some("generate") => generate(&args[1..]),
+        Some("render") => render(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("help") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        _ => return None,
+    };
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            println!("{}", e);
+            Some(1)
+        },
+    }
+}
+
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+fn number_flag<T: std::str::FromStr>(args: &[String], name: &str) -> Result<T, String> {
+    let value = flag(args, name).ok_or(format!("missing {}\n{}", name, USAGE))?;
+    value.parse().map_err(|_| format!("{} {} is not a number", name, value))
+}
+
+// the first argument thats not a flag or a flags value
+fn positional<'a>(args: &'a [String], what: &str) -> Result<&'a str, String> {
+    let mut i = 0;
+    while i < args.len() {
+        if args[i].starts_with("--") || args[i] == "-o" {
+            i += 2;
+            continue;
+        }
+        return Ok(&args[i]);
+    }
+    Err(format!("missing {}\n{}", what, USAGE))
+}
+
+fn try_limit(args: &[String]) -> Result<u64, String> {
+    if flag(args, "--limit").is_some() { number_flag(args, "--limit") } else { Ok(DEFAULT_TRY_LIMIT) }
+}
+
+fn load(path: &str) -> Result<LevelMetadata, String> {
+    load_level_file(Path::new(path)).map_err(|e| e.to_string())
+}
+
+// text format for .tlevel, json for anything else, same as the editor
+fn write_level(metadata: &LevelMetadata, path: &str) -> Result<(), String> {
+    let result = if is_text_level(Path::new(path)) { metadata.save_text(path) } else { metadata.save(path) };
+    result.map_err(|e| format!("couldn't write {}: {}", path, e))?;
+    println!("wrote {}", path);
+    Ok(())
+}
+
+fn solve(args: &[String]) -> Result<(), String> {
+    let path = positional(args, "level file")?;
+    let limit = try_limit(args)?;
+    let metadata = load(path)?;
+    let solution = match metadata.level.solve_limited(limit) {
+        Ok(Some(solution)) => solution,
+        Ok(None) => return Err(format!("{}: no solution", path)),
+        Err(tried) => return Err(format!("{}: gave up after trying {} tiles, raise --limit", path, tried)),
+    };
+    let solved = LevelMetadata { level: solution, source: None, ..metadata };
+    match flag(args, "-o") {
+        Some(out) => write_level(&solved, out),
+        None => {
+            print!("{}", level_to_text(&solved));
+            Ok(())
+        },
+    }
+}
+
+fn validate(args: &[String]) -> Result<(), String> {
+    let dir = positional(args, "levels directory")?;
+    if !Path::new(dir).is_dir() {
+        return Err(format!("{} is not a directory", dir));
+    }
+    let limit = try_limit(args)?;
+    let (packs, errors) = load_packs(dir);
+    let mut problems = errors.len();
+    for e in errors.iter() {
+        println!("{}", e);
+    }
+
+    let mut checked = 0;
+    for pack in packs.iter() {
+        for metadata in pack.levels.iter() {
+            checked += 1;
+            let name = level_name(metadata);
+            if metadata.level.tile_palette.is_empty() {
+                println!("{}: empty palette", name);
+                problems += 1;
+            } else {
+                match metadata.level.solve_limited(limit) {
+                    Ok(Some(_)) => {},
+                    Ok(None) => {
+                        println!("{}: no solution", name);
+                        problems += 1;
+                    },
+                    Err(tried) => {
+                        println!("{}: gave up after trying {} tiles, raise --limit", name, tried);
+                        problems += 1;
+                    },
+                }
+            }
+        }
+        for group in pack.duplicates() {
+            let names: Vec<String> = group.iter().map(|i| level_name(&pack.levels[*i])).collect();
+            println!("{}: same puzzle: {}", pack.meta.name, names.join(", "));
+            problems += group.len() - 1;
+        }
+    }
+
+    println!("checked {} levels in {} packs, {} problems", checked, packs.len(), problems);
+    if problems == 0 { Ok(()) } else { Err(format!("{} failed validation", dir)) }
+}
+
+fn generate(args: &[String]) -> Result<(), String> {
+    let w: i32 = number_flag(args, "--w")?;
+    let h: i32 = number_flag(args, "--h")?;
+    let colours: i32 = number_flag(args, "--colours")?;
+    let seed: u32 = number_flag(args, "--seed")?;
+    if w < 1 || h < 1 {
+        return Err(format!("{}x{} is not a board", w, h));
+    }
+    if colours < 1 || colours > COLOURS.len() as i32 {
+        return Err(format!("--colours has to be 1 to {}", COLOURS.len()));
+    }
+
+    let mut metadata = LevelMetadata::new(Level::generate(w, h, colours, seed));
+    metadata.name = format!("generated {}", seed);
+    match flag(args, "-o") {
+        Some(out) => write_level(&metadata, out),
+        None => {
+            print!("{}", level_to_text(&metadata));
+            Ok(())
+        },
+    }
+}
+
+fn render(args: &[String]) -> Result<(), String> {
+    let path = positional(args, "level file")?;
+    let out = flag(args, "-o").ok_or(format!("missing -o out.png\n{}", USAGE))?;
+    let size = if flag(args, "--size").is_some() { number_flag(args, "--size")? } else { DEFAULT_RENDER_SIZE };
+    if size == 0 {
+        return Err(String::from("--size has to be more than 0"));
+    }
+    let metadata = load(path)?;
+    level_thumbnail(&metadata.level, size, ImageBufferA::new_from_bytes(ATLAS_PNG)).dump_to_file(out);
+    println!("wrote {}", out);
+    Ok(())
+}
+
+fn stats(args: &[String]) -> Result<(), String> {
+    let dir = positional(args, "levels directory")?;
+    if !Path::new(dir).is_dir() {
+        return Err(format!("{} is not a directory", dir));
+    }
+    let limit = try_limit(args)?;
+    let (packs, errors) = load_packs(dir);
+    for e in errors.iter() {
+        println!("{}", e);
+    }
+
+    // tab separated so it can go straight into a spreadsheet
+    println!("pack\tlevel\tsize\tcolours\tpalette\tcomplexity\tsolutions\ttried");
+    for pack in packs.iter() {
+        for metadata in pack.levels.iter() {
+            let level = &metadata.level;
+            let mut colours: Vec<u8> = level.tile_palette.iter().flatten().copied().collect();
+            colours.sort();
+            colours.dedup();
+            let (solutions, tried) = level.count_solutions(SOLUTION_LIMIT, limit);
+            // gave up part way either way, so theres at least that many
+            let solutions = if solutions >= SOLUTION_LIMIT || tried >= limit { format!("{}+", solutions) } else { solutions.to_string() };
+            println!("{}\t{}\t{}x{}\t{}\t{}\t{}\t{}\t{}",
+                pack.meta.name, level_name(metadata), level.w, level.h, colours.len(),
+                level.tile_palette.len(), level.complexity(), solutions, tried);
+        }
+    }
+    Ok(())
+}
+
+// the file name when theres one, they are what people go looking for
+fn level_name(metadata: &LevelMetadata) -> String {
+    match metadata.source.as_ref() {
+        Some(path) => path.display().to_string(),
+        None => metadata.name.clone(),
+    }
+}
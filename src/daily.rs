@@ -0,0 +1,59 @@
+use crate::kmath::*;
+use crate::level::*;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// UTC so everyone gets the same puzzle on the same day regardless of where they are
+pub fn today() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    date_from_days((secs / 86400) as i64)
+}
+
+pub fn date_from_days(days: i64) -> String {
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+pub fn days_from_date(date: &str) -> Option<i64> {
+    let mut parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    match (parts.next()?, parts.next()?, parts.next()?) {
+        (Some(y), Some(m), Some(d)) => Some(days_from_civil(y, m as u32, d as u32)),
+        _ => None,
+    }
+}
+
+// days since 1970-01-01 to (year, month, day), from Howard Hinnant's date algorithms
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (if m > 2 { m - 3 } else { m + 9 }) as i64;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+pub fn daily_seed(date: &str) -> u32 {
+    date.bytes().fold(0u32, |h, b| khash(h.wrapping_add(b as u32)))
+}
+
+pub fn daily_level(date: &str) -> Level {
+    let seed = daily_seed(date);
+    let w = 3 + (khash(seed) % 3) as i32;
+    let h = 3 + (khash(seed.wrapping_add(1)) % 3) as i32;
+    let num_colours = 2 + (khash(seed.wrapping_add(2)) % 3) as i32;
+    Level::generate(w, h, num_colours, seed)
+}
@@ -1,24 +1,38 @@
 use crate::level::*;
 use crate::kmath::*;
-use crate::renderer::*;
 use crate::application::*;
 use crate::game::*;
-use crate::level_menu::*;
+use crate::pack_menu::*;
+use crate::pack::*;
+use crate::migrate::*;
 use crate::manifest::*;
 use crate::kgui::*;
-use crate::rendererUV::TriangleBufferUV;
+use crate::rendererUV::{TextStyle, Align};
+use crate::progress::*;
+use crate::level_code::*;
+use crate::level_text::*;
+use crate::recovery::*;
+use crate::raster::*;
+use crate::kimg::*;
+use crate::bindings::*;
 
 use std::collections::HashMap;
 use std::fmt::*;
+use std::path::{Path, PathBuf};
 
-use glutin::event::VirtualKeyCode;
 
+use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Copy, Debug)]
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum EditorCommand {
     PlaceTile(i32, i32),
     ClearTile(i32, i32),
     PickTile(i32, i32),
+    // ctrl click, the selection is what fill and clear work on
+    ToggleSelected(i32, i32),
+    FillSelection,
+    ClearSelection,
 
     SelectTileWedge(i32),
     RotateRight,
@@ -30,73 +44,197 @@ pub enum EditorCommand {
     PlacePaletteTile(i32),
     PickPaletteTile(i32),
     RemovePaletteTile(i32),
+    // dragged from the palette and let go on the board
+    DropPaletteTile(i32, i32, i32),
+
+    Undo,
+    Redo,
 
     PlayLevel,
     SaveLevel,
+    SaveLevelText,
+    SaveLevelAs,
     LoadLevel,
+    ExportCode,
+    ImportCode,
+    ExportThumbnail,
+    Help,
 }
 
+impl EditorCommand {
+    fn writes_files(&self) -> bool {
+        matches!(self, EditorCommand::SaveLevel | EditorCommand::SaveLevelText | EditorCommand::ExportCode | EditorCommand::ExportThumbnail)
+    }
+}
 
-pub struct Button {
-    rect: Rect,
-    command: EditorCommand,
-    hotkey: VirtualKeyCode,
-    appearance: ButtonAppearance,
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextField {
+    ImportCode,
+    // save as goes through these in order
+    Name,
+    Author,
 }
 
-pub enum ButtonAppearance {
-    Colour(Vec3),
-    Texture(i32),
+impl TextField {
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            TextField::ImportCode => c.is_ascii_alphanumeric() || c == '-' || c == '_',
+            TextField::Name | TextField::Author => !c.is_control(),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            TextField::ImportCode => "paste (ctrl+v) or type a level code, or return to read level.code",
+            TextField::Name => "name",
+            TextField::Author => "author, return saves",
+        }
+    }
+
+    fn colour(&self) -> Vec3 {
+        match self {
+            TextField::ImportCode => Vec3::new(0.05, 0.05, 0.05),
+            TextField::Name => Vec3::new(0.05, 0.05, 0.2),
+            TextField::Author => Vec3::new(0.05, 0.2, 0.05),
+        }
+    }
 }
 
 pub struct Editor {
     pub level: Level,
     pub place_tile: Tile,
     pub tile_selection: usize,
+    pub selection: Vec<(i32, i32)>,
+    pub camera: BoardCamera,
+    // the palette tile being dragged onto the board and where the mouse has it
+    pub palette_drag: Option<(i32, Vec2)>,
+    pub progress: SharedProgress,
+    pub bindings: SharedBindings,
+
+    pub name: String,
+    pub author: String,
+    pub rating: i32,
+    pub par: Option<f32>,
+    // where saving goes without asking, None until the level is loaded or saved as something
+    pub source: Option<PathBuf>,
+    // a different level that the last save would have overwritten, saving there again goes ahead
+    pub pending_overwrite: Option<PathBuf>,
+
+    // while this is Some the keyboard goes to it
+    pub text_field: Option<(TextField, String)>,
+
+    // every distinct state of the level, undo_pos is the one showing
+    pub history: Vec<Level>,
+    pub undo_pos: usize,
+
+    pub autosave: bool,
+    autosave_timer: f32,
+    last_autosave: String,
+    // set for replays and tests, anything that would write a file just says so instead
+    pub no_disk: bool,
 }
 
+const UNDO_LIMIT: usize = 200;
+const AUTOSAVE_SECONDS: f32 = 5.0;
+// the rating slider goes from 0 to this, older levels rated higher keep it until the slider is moved
+const MAX_RATING: i32 = 5;
+
 impl Scene for Editor {
-    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, TriangleBuffer, Option<TriangleBufferUV>) {
-        let click = inputs.events.iter().any(|e| match e {KEvent::MouseLeft(true) => true, _ => false});
-        let clickr = inputs.events.iter().any(|e| match e {KEvent::MouseRight(true) => true, _ => false});
-        let clickm = inputs.events.iter().any(|e| match e {KEvent::MouseMiddle(true) => true, _ => false});
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>) {
+        let click = inputs.lmb_pressed();
+        let clickr = inputs.rmb_pressed();
+        let clickm = inputs.mmb_pressed();
         let mut so = SceneOutcome::None;
-        let mut buf = TriangleBuffer::new(inputs.screen_rect);
-        let mut buf_uv = TriangleBufferUV::new(inputs.screen_rect, ATLAS_W, ATLAS_H);
+        let mut gui = Gui::new(&inputs);
 
         let center_pane = inputs.screen_rect.child(0.15, 0.0, 0.7, 1.0);
         let left_pane = Rect::new(0.0, 0.0, (inputs.screen_rect.w - center_pane.w) / 2.0, 1.0);
-        let mut button = |x, y, cmd, icon| {
+        let buttons = [
+            (0, 0, EditorCommand::PlayLevel, PLAY, "play"),
+            (1, 0, EditorCommand::LoadLevel, OPEN, "open"),
+            (0, 1, EditorCommand::SaveLevel, SAVE, "save"),
+            (0, 4, EditorCommand::AddPaletteTile, PLUS_TAPE, "add to palette"),
+        ];
+        for (x, y, cmd, icon, tooltip) in buttons {
             let button_rect = left_pane.grid_child(x, y, 2, 5).dilate(-0.01);
-            buf.draw_rect(button_rect, Vec3::new(0.1, 0.1, 0.1), 5.0);
-            buf_uv.draw_sprite(button_rect.fit_center_square(), icon, 6.0);
-            if click && button_rect.contains(inputs.mouse_pos) {
-                self.handle_command(cmd)
-            } else {
-                SceneOutcome::None
+            match chord_for(&self.bindings.editor, cmd) {
+                Some(chord) => gui.tooltip(button_rect, &format!("{} ({})", tooltip, chord)),
+                None => gui.tooltip(button_rect, tooltip),
             }
-        };
-        match so { SceneOutcome::None => {so = button(0, 0, EditorCommand::PlayLevel, PLAY) }, _ => {}};
-        match so { SceneOutcome::None => {so = button(1, 0, EditorCommand::LoadLevel, OPEN) }, _ => {}};
-        button(0, 1, EditorCommand::SaveLevel, SAVE);
-        button(0, 2, EditorCommand::AlterDims(1, 0), PLUS_W);
-        button(1, 2, EditorCommand::AlterDims(-1, 0), MINUS_W);
-        button(0, 3, EditorCommand::AlterDims(0, 1), PLUS_H);
-        button(1, 3, EditorCommand::AlterDims(0, -1), MINUS_H);
-        button(0, 4, EditorCommand::AddPaletteTile, PLUS_TAPE);
-
-        let (maybe_rollover_palette, maybe_rollover_grid) = self.level.frame(&mut buf, &mut buf_uv, center_pane, &inputs, None);
+            if gui.button(button_rect, ButtonAppearance::Sprite(icon)) {
+                let outcome = self.handle_command(cmd);
+                if let SceneOutcome::None = so {
+                    so = outcome;
+                }
+            }
+        }
+        let (mut w, mut h) = (self.level.w, self.level.h);
+        let w_rect = left_pane.child(0.0, 0.4, 1.0, 0.2).dilate(-0.01).child(0.0, 0.25, 1.0, 0.5);
+        gui.tooltip(w_rect, "width");
+        if gui.spinner(w_rect, &mut w, 1, i32::MAX) {
+            self.handle_command(EditorCommand::AlterDims(w - self.level.w, 0));
+        }
+        let h_rect = left_pane.child(0.0, 0.6, 1.0, 0.2).dilate(-0.01).child(0.0, 0.25, 1.0, 0.5);
+        gui.tooltip(h_rect, "height");
+        if gui.spinner(h_rect, &mut h, 1, i32::MAX) {
+            self.handle_command(EditorCommand::AlterDims(0, h - self.level.h));
+        }
+        let rating_cell = left_pane.grid_child(1, 1, 2, 5).dilate(-0.01);
+        let rating_rect = rating_cell.child(0.0, 0.35, 1.0, 0.4);
+        gui.tooltip(rating_rect, "rating");
+        let mut rating = self.rating as f32;
+        if gui.slider(rating_rect, &mut rating, 0.0, MAX_RATING as f32) {
+            self.rating = rating.round() as i32;
+        }
+        gui.buf_uv.draw_text(rating_cell.child(0.0, 0.05, 1.0, 0.25), &format!("rating {}", self.rating), Vec3::new(1.0, 1.0, 1.0), gui.depth + 1.0);
+        let autosave_rect = left_pane.grid_child(1, 4, 2, 5).dilate(-0.01);
+        gui.tooltip(autosave_rect, "autosave");
+        gui.toggle(autosave_rect, &mut self.autosave, ButtonAppearance::Text("auto"));
+
+        // a drag that starts on the palette carries that tile, the board doesnt get painted on the way
+        if let Some(origin) = inputs.drag_started() {
+            self.palette_drag = (0..self.level.tile_palette.len() as i32)
+                .find(|i| self.level.palette_tile_rect(center_pane, *i).contains(origin.centroid()))
+                .map(|i| (i, origin.centroid()));
+        }
+        if let (Some((_, pos)), Some((_, to))) = (self.palette_drag.as_mut(), inputs.drag_moved()) {
+            *pos = to;
+        }
+        // right clicking can take the tile out of the palette mid drag
+        self.palette_drag = self.palette_drag.filter(|(i, _)| (*i as usize) < self.level.tile_palette.len());
+        let dragged_palette = self.palette_drag.map(|(i, _)| i);
+        // wheel zooms like in game, shift drag pans since middle and space are already taken
+        let panning = inputs.held_lmb && inputs.modifiers.shift;
+        let camera_busy = self.camera.update(&self.level, center_pane, &inputs, panning);
+        let mut board = self.camera.layer(&self.level, center_pane, inputs.screen_rect, Vec2::new(0.0, 0.0));
+        let (maybe_rollover_palette, maybe_rollover_grid) = self.level.frame_camera(&mut gui.buf, &mut board, center_pane, &inputs, None, &self.camera);
+        let maybe_rollover_grid = if camera_busy { None } else { maybe_rollover_grid };
         if let Some(rollover_palette) = maybe_rollover_palette {
-            if click || inputs.held_lmb {
+            let tile_rect = self.level.palette_tile_rect(center_pane, rollover_palette);
+            if inputs.clicked(tile_rect) && inputs.drag_origin.is_none() {
                 self.handle_command(EditorCommand::PlacePaletteTile(rollover_palette));
             } else if clickr {
                 self.handle_command(EditorCommand::RemovePaletteTile(rollover_palette));
             } else if clickm || inputs.held_mmb {
                 self.handle_command(EditorCommand::PickPaletteTile(rollover_palette));
             }
+            let steps = inputs.wheel_steps(tile_rect);
+            if steps != 0 {
+                let n = self.level.tile_palette.len() as i32;
+                let current = self.level.tile_palette.iter().position(|t| *t == self.place_tile).map_or(-1, |i| i as i32);
+                let next = if current < 0 && steps < 0 { n + steps } else { current + steps };
+                self.handle_command(EditorCommand::PickPaletteTile(next.rem_euclid(n)));
+            }
         }
         if let Some((x, y)) = maybe_rollover_grid {
-            if click || inputs.held_lmb {
+            let painting = dragged_palette.is_none() && !inputs.modifiers.ctrl;
+            if click && inputs.modifiers.ctrl {
+                self.handle_command(EditorCommand::ToggleSelected(x, y));
+            } else if (click || inputs.held_lmb) && painting {
+                if click {
+                    self.selection.clear();
+                }
                 self.handle_command(EditorCommand::PlaceTile(x, y));
             } else if clickr || inputs.held_rmb {
                 self.handle_command(EditorCommand::ClearTile(x, y));
@@ -104,17 +242,62 @@ impl Scene for Editor {
                 self.handle_command(EditorCommand::PickTile(x, y));
             }
         }
+        if let Some((i, pos)) = self.palette_drag {
+            match (inputs.drag_ended(), maybe_rollover_grid) {
+                (Some(_), Some((x, y))) => {self.handle_command(EditorCommand::DropPaletteTile(i, x, y));},
+                (Some(_), None) => {},
+                // the tile follows the mouse until its let go
+                (None, _) => {
+                    let size = self.level.palette_tile_rect(center_pane, i).w;
+                    let ghost = Rect::new_centered(pos.x, pos.y, size, size);
+                    draw_tile_depth(&mut gui.buf, ghost, self.level.tile_palette[i as usize], 15.0);
+                },
+            }
+            if inputs.drag_ended().is_some() {
+                self.palette_drag = None;
+            }
+        }
+        // resizing and undo can leave some of it off the board
+        self.selection = self.selected_cells();
+        if let Some((x, y)) = maybe_rollover_grid {
+            if dragged_palette.is_none() && !inputs.modifiers.ctrl && self.level.get_tile(x, y) != Some(self.place_tile) {
+                self.level.draw_ghost(&mut board.tris, center_pane, x, y, self.place_tile);
+            }
+        }
+        for (x, y) in self.selection.iter() {
+            let cell = self.level.board_cell_rect(center_pane, *x, *y);
+            board.tris.draw_rect(cell.child(0.0, 0.0, 0.2, 0.2), Vec3::new(1.0, 1.0, 1.0), 12.0);
+            board.tris.draw_rect(cell.child(0.8, 0.8, 0.2, 0.2), Vec3::new(1.0, 1.0, 1.0), 12.0);
+        }
+
+        // fields opened this frame start taking keys next frame, so the key that opened it isnt typed in
+        let typing = self.text_field.is_some();
+        if let Some((field, mut text)) = self.text_field.clone() {
+            let field_rect = center_pane.child(0.05, 0.45, 0.9, 0.1);
+            gui.depth = 20.0;
+            gui.buf.draw_rect(field_rect.child(0.0, -0.6, 1.0, 1.6).dilate(0.01), field.colour(), 19.0);
+            let style = TextStyle { size: 0.03, align: Align::Left, wrap: false };
+            gui.buf_uv.draw_text_style(field_rect.child(0.0, -0.5, 1.0, 0.4), field.label(), Vec3::new(1.0, 1.0, 1.0), 21.0, style);
+            let mut focused = true;
+            match gui.text_input(field_rect, &mut text, &mut focused, |c| field.accepts(c)) {
+                TextEdit::Submitted => self.submit_text(field, text),
+                TextEdit::Cancelled => self.text_field = None,
+                // clicking off it drops it, same as escape
+                _ if !focused => self.text_field = None,
+                _ => self.text_field = Some((field, text)),
+            }
+        }
 
-        let mut scene_outcomes: Vec<SceneOutcome> = inputs.events.iter().filter_map(|e| match e {
-            KEvent::Keyboard(VirtualKeyCode::Q, true) => Some(EditorCommand::RotateLeft),
-            KEvent::Keyboard(VirtualKeyCode::E, true) => Some(EditorCommand::RotateRight),
-            KEvent::Keyboard(VirtualKeyCode::Space, true) => Some(EditorCommand::PlayLevel),
-            KEvent::Keyboard(VirtualKeyCode::O, true) => Some(EditorCommand::LoadLevel),
-            KEvent::Keyboard(VirtualKeyCode::S, true) => Some(EditorCommand::SaveLevel),
-            _ => None,
-        }).map(|c| self.handle_command(c)).collect();
+        let commands = if typing { Vec::new() } else { actions(&self.bindings.editor, &inputs) };
+        let mut scene_outcomes: Vec<SceneOutcome> = commands.into_iter().map(|c| self.handle_command(c)).collect();
         scene_outcomes.push(so);
 
+        self.autosave_timer += inputs.dt;
+        if self.autosave && !self.no_disk && self.autosave_timer > AUTOSAVE_SECONDS {
+            self.autosave_timer = 0.0;
+            self.autosave();
+        }
+
         let right_pane = Rect::new(center_pane.right(), 0.0, (inputs.screen_rect.w - center_pane.w) / 2.0, 1.0);
 
         let place_tile_pane = Rect::new(right_pane.x, 0.0, right_pane.w, right_pane.w);
@@ -127,32 +310,46 @@ impl Scene for Editor {
                 println!("spaget {}", i);
             }
             if self.tile_selection == i {
-                buf.draw_tri(place_tri.dilate(0.05), Vec3::new(1.0, 1.0, 1.0), 10.0);
-                buf.draw_tri(place_tri, COLOURS[self.place_tile[i] as usize], 11.0);
+                gui.buf.draw_tri(place_tri.dilate(0.05), Vec3::new(1.0, 1.0, 1.0), 10.0);
+                gui.buf.draw_tri(place_tri, COLOURS[self.place_tile[i] as usize], 11.0);
             } else {
-                buf.draw_tri(place_tri, COLOURS[self.place_tile[i] as usize], 9.0);
+                gui.buf.draw_tri(place_tri, COLOURS[self.place_tile[i] as usize], 9.0);
             }
         }
 
+        gui.depth = 9.0;
         let right_bot_pane = Rect::new(place_tile_pane.x, place_tile_pane.bot(), place_tile_pane.w, right_pane.h - place_tile_pane.h);
         for i in 0..COLOURS.len() {
             let colour_rect = right_bot_pane.grid_child(0, i as i32, 1, COLOURS.len() as i32);
-            buf.draw_rect(colour_rect, COLOURS[i], 10.0);
-            if click && colour_rect.contains(inputs.mouse_pos) {
+            if gui.button(colour_rect, ButtonAppearance::Colour(COLOURS[i])) {
                 self.place_tile[self.tile_selection] = i as u8;
             }
+            // double click paints the whole tile
+            if inputs.double_clicked() && colour_rect.contains(inputs.mouse_pos) {
+                self.place_tile = [i as u8; 4];
+            }
         }
 
-        buf.draw_rect(left_pane, Vec3::new(0.2, 0.2, 0.2), 1.0);
-        buf.draw_rect(right_pane, Vec3::new(0.2, 0.2, 0.2), 1.0);
+        // save is waiting on a second press to overwrite something
+        if self.pending_overwrite.is_some() {
+            gui.buf.draw_rect(left_pane.grid_child(0, 1, 2, 5), Vec3::new(0.8, 0.1, 0.1), 4.0);
+        }
+
+        gui.buf.draw_rect(left_pane, Vec3::new(0.2, 0.2, 0.2), 1.0);
+        gui.buf.draw_rect(right_pane, Vec3::new(0.2, 0.2, 0.2), 1.0);
 
-        (scene_outcomes.remove(0), buf, Some(buf_uv))
+        let (buf, buf_uv) = gui.finish();
+        (scene_outcomes.remove(0), vec![board, Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])
     }
 
     fn handle_signal(&mut self, signal: SceneSignal) -> SceneOutcome {
         match signal {
-            SceneSignal::LevelChoice(level) => {
-                self.level = level;
+            SceneSignal::LevelChoice(metadata) => {
+                self.set_level(metadata);
+                return SceneOutcome::None
+            },
+            SceneSignal::RestoreEditor(recovery) => {
+                self.restore(*recovery);
                 return SceneOutcome::None
             },
             _ => {SceneOutcome::None},
@@ -161,16 +358,198 @@ impl Scene for Editor {
 }
 
 impl Editor {
-    pub fn new() -> Editor {
-        Editor {
-            level: Level::new(4,4),
+    pub fn new(progress: SharedProgress, bindings: SharedBindings) -> Editor {
+        let level = Level::new(4,4);
+        let mut editor = Editor {
+            history: vec![level.clone()],
+            undo_pos: 0,
+            autosave: true,
+            autosave_timer: 0.0,
+            last_autosave: String::new(),
+            no_disk: false,
+            level,
             place_tile: [0; 4],
             tile_selection: 0,
+            selection: Vec::new(),
+            camera: BoardCamera::default(),
+            palette_drag: None,
+            progress,
+            bindings,
+            name: String::from("untitled"),
+            author: String::new(),
+            rating: 0,
+            par: None,
+            source: None,
+            pending_overwrite: None,
+            text_field: None,
+        };
+        editor.last_autosave = serde_json::to_string(&editor.recovery()).unwrap();
+        editor
+    }
+
+    pub fn set_level(&mut self, metadata: LevelMetadata) {
+        self.level = metadata.level;
+        self.name = metadata.name;
+        self.author = metadata.author;
+        self.rating = metadata.rating;
+        self.par = metadata.par;
+        self.source = metadata.source;
+        self.pending_overwrite = None;
+        self.selection.clear();
+        self.palette_drag = None;
+        self.camera = BoardCamera::default();
+        self.history = vec![self.level.clone()];
+        self.undo_pos = 0;
+        // a freshly opened level has nothing to recover
+        self.last_autosave = serde_json::to_string(&self.recovery()).unwrap();
+    }
+
+    pub fn recovery(&self) -> EditorRecovery {
+        EditorRecovery {
+            metadata: self.metadata(),
+            source: self.source.clone(),
+            place_tile: self.place_tile,
+            tile_selection: self.tile_selection,
+            history: self.history.clone(),
+            undo_pos: self.undo_pos,
+        }
+    }
+
+    pub fn restore(&mut self, recovery: EditorRecovery) {
+        self.set_level(LevelMetadata { source: recovery.source, ..recovery.metadata });
+        self.place_tile = recovery.place_tile;
+        self.tile_selection = recovery.tile_selection.min(3);
+        self.history = recovery.history;
+        self.undo_pos = recovery.undo_pos;
+        println!("restored editor from {}", RECOVERY_PATH);
+    }
+
+    // only touches the disk when something changed since last time, and never before the first edit
+    fn autosave(&mut self) {
+        if self.history.len() <= 1 {
+            return;
+        }
+        let str = serde_json::to_string(&self.recovery()).unwrap();
+        if str != self.last_autosave {
+            EditorRecovery::write_str(&str);
+            self.last_autosave = str;
+        }
+    }
+
+    // whats selected and still on the board
+    fn selected_cells(&self) -> Vec<(i32, i32)> {
+        self.selection.iter().copied().filter(|(x, y)| *x < self.level.w && *y < self.level.h).collect()
+    }
+
+    // called after every command, adds an undo step if the level is different to the current one
+    fn record_history(&mut self) {
+        if self.history[self.undo_pos] == self.level {
+            return;
+        }
+        self.history.truncate(self.undo_pos + 1);
+        self.history.push(self.level.clone());
+        if self.history.len() > UNDO_LIMIT {
+            self.history.remove(0);
+        }
+        self.undo_pos = self.history.len() - 1;
+    }
+
+    pub fn metadata(&self) -> LevelMetadata {
+        LevelMetadata {
+            version: LEVEL_FORMAT_VERSION,
+            level: self.level.clone(),
+            name: self.name.clone(),
+            author: self.author.clone(),
+            rating: self.rating,
+            par: self.par,
+            source: self.source.clone(),
+        }
+    }
+
+    // Return with no code typed reads LEVEL_CODE_PATH instead, for codes handed over as a file
+    fn submit_text(&mut self, field: TextField, text: String) {
+        match field {
+            TextField::ImportCode => {
+                let code = if text.is_empty() {
+                    match std::fs::read_to_string(LEVEL_CODE_PATH) {
+                        Ok(code) => code,
+                        Err(e) => {
+                            println!("couldn't read {}: {}", LEVEL_CODE_PATH, e);
+                            return;
+                        },
+                    }
+                } else {
+                    text
+                };
+                match decode_level(code.trim()) {
+                    Ok(level) => {
+                        self.set_level(LevelMetadata::new(level));
+                        self.text_field = None;
+                    },
+                    Err(e) => println!("couldn't import: {}", e),
+                }
+            },
+            TextField::Name => {
+                if !text.trim().is_empty() {
+                    self.name = text.trim().to_string();
+                }
+                self.text_field = Some((TextField::Author, self.author.clone()));
+            },
+            // the rating is the slider on the left
+            TextField::Author => {
+                self.author = text.trim().to_string();
+                self.text_field = None;
+                let text_format = self.source.as_ref().map_or(false, |p| is_text_level(p));
+                let path = self.named_path(if text_format { LEVEL_TEXT_EXTENSION } else { LEVEL_EXTENSION });
+                self.save_to(path);
+            },
+        }
+    }
+
+    fn named_path(&self, extension: &str) -> PathBuf {
+        let slug: String = self.name.trim().chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect();
+        Path::new(LEVELS_DIR).join(format!("{}.{}", if slug.is_empty() { "untitled" } else { &slug }, extension))
+    }
+
+    // Overwriting where the level came from is fine, anything else gets one warning first
+    fn save_to(&mut self, path: PathBuf) {
+        if self.no_disk {
+            println!("not saving {}, no disk", path.display());
+            return;
+        }
+        let different = path.exists()
+            && self.source.as_ref() != Some(&path)
+            && load_level_file(&path).map_or(true, |lm| lm.level.hash() != self.level.hash());
+        if different && self.pending_overwrite.as_ref() != Some(&path) {
+            println!("{} is a different level, save again to overwrite it", path.display());
+            self.pending_overwrite = Some(path);
+            return;
+        }
+        self.pending_overwrite = None;
+
+        let metadata = self.metadata();
+        let path_str = path.to_string_lossy().to_string();
+        let result = if is_text_level(&path) { metadata.save_text(&path_str) } else { metadata.save(&path_str) };
+        match result {
+            Ok(()) => {
+                println!("saved {}", path.display());
+                self.source = Some(path);
+                // the file has it all now, until the next change
+                if self.autosave {
+                    EditorRecovery::remove();
+                }
+                self.last_autosave = serde_json::to_string(&self.recovery()).unwrap();
+            },
+            Err(e) => println!("couldn't save {}: {}", path.display(), e),
         }
     }
 
     pub fn handle_command(&mut self, command: EditorCommand) -> SceneOutcome {
         println!("Editor Command: {:?}", command);
+        if self.no_disk && command.writes_files() {
+            println!("not running {:?}, no disk", command);
+            return SceneOutcome::None;
+        }
         match command {
             EditorCommand::AddPaletteTile => {self.level.tile_palette.push(self.place_tile)},
             EditorCommand::PlacePaletteTile(i) => {self.level.tile_palette[i as usize] = self.place_tile},
@@ -197,23 +576,89 @@ impl Editor {
                 self.level.set_tile(x, y, self.place_tile);
                 self.level.set_locked(x, y, true);
             },
+            EditorCommand::ToggleSelected(x, y) => {
+                match self.selection.iter().position(|p| *p == (x, y)) {
+                    Some(i) => {self.selection.remove(i);},
+                    None => self.selection.push((x, y)),
+                }
+            },
+            EditorCommand::FillSelection => {
+                for (x, y) in self.selected_cells() {
+                    self.level.set_tile(x, y, self.place_tile);
+                    self.level.set_locked(x, y, true);
+                }
+            },
+            EditorCommand::ClearSelection => {
+                for (x, y) in self.selected_cells() {
+                    self.level.clear_tile(x, y);
+                    self.level.set_locked(x, y, false);
+                }
+            },
+            EditorCommand::DropPaletteTile(i, x, y) => {
+                self.level.set_tile(x, y, self.level.tile_palette[i as usize]);
+                self.level.set_locked(x, y, true);
+            },
             EditorCommand::SelectTileWedge(i) => {self.tile_selection = i as usize},
 
             EditorCommand::AlterDims(dx, dy) => {
                 self.level.resize(self.level.w + dx, self.level.h + dy);
             },
 
-            EditorCommand::PlayLevel => {return SceneOutcome::Push(Box::new(Game {level: self.level.clone(), place_tile: self.level.tile_palette[0], place_idx: 0}))},
+            EditorCommand::Undo => {
+                if self.undo_pos > 0 {
+                    self.undo_pos -= 1;
+                    self.level = self.history[self.undo_pos].clone();
+                }
+            },
+            EditorCommand::Redo => {
+                if self.undo_pos + 1 < self.history.len() {
+                    self.undo_pos += 1;
+                    self.level = self.history[self.undo_pos].clone();
+                }
+            },
+
+            EditorCommand::PlayLevel => {return SceneOutcome::Push(Box::new(Game::new(self.level.clone(), self.progress.clone(), self.bindings.clone())))},
             EditorCommand::SaveLevel => {
-                let hash = self.level.hash();
-                let path = format!("levels/{}.level", hash);
-                let metadata = LevelMetadata {
-                    level: self.level.clone(), name: String::from("untitled"), rating: 69,
+                let path = self.source.clone().unwrap_or_else(|| self.named_path(LEVEL_EXTENSION));
+                self.save_to(path);
+            },
+            EditorCommand::SaveLevelText => {
+                let path = match self.source.as_ref() {
+                    Some(source) => source.with_extension(LEVEL_TEXT_EXTENSION),
+                    None => self.named_path(LEVEL_TEXT_EXTENSION),
                 };
-                metadata.save(&path);
+                self.save_to(path);
+            },
+            EditorCommand::SaveLevelAs => {self.text_field = Some((TextField::Name, self.name.clone()))},
+            EditorCommand::LoadLevel => {return SceneOutcome::Push(Box::new(PackMenu::new(self.progress.clone(), self.bindings.clone())))},
+            EditorCommand::Help => {return SceneOutcome::Push(Box::new(HelpScreen::new("editor", &self.bindings.editor)))},
+            EditorCommand::ExportCode => {
+                match encode_level(&self.level) {
+                    Ok(code) => {
+                        println!("level code: {}", code);
+                        match std::fs::write(LEVEL_CODE_PATH, &code) {
+                            Ok(()) => println!("wrote {}", LEVEL_CODE_PATH),
+                            Err(e) => println!("couldn't write {}: {}", LEVEL_CODE_PATH, e),
+                        }
+                    },
+                    Err(e) => println!("couldn't export: {}", e),
+                }
+            },
+            EditorCommand::ImportCode => {self.text_field = Some((TextField::ImportCode, String::new()))},
+            EditorCommand::ExportThumbnail => {
+                // kept out of the levels dir so the loader doesn't trip over them
+                let path = self.named_path("png");
+                let path = Path::new(THUMBNAILS_DIR).join(path.file_name().unwrap());
+                match std::fs::create_dir_all(THUMBNAILS_DIR) {
+                    Ok(()) => {
+                        level_thumbnail(&self.level, 256, ImageBufferA::new_from_file(ATLAS_PATH)).dump_to_file(&path.to_string_lossy());
+                        println!("saved {}", path.display());
+                    },
+                    Err(e) => println!("couldn't make {}: {}", THUMBNAILS_DIR, e),
+                }
             },
-            EditorCommand::LoadLevel => {return SceneOutcome::Push(Box::new(LevelMenu::new()))},
         }
+        self.record_history();
         return SceneOutcome::None;
     }
 }
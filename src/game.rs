@@ -4,7 +4,11 @@ use crate::renderer::*;
 use crate::kmath::*;
 use crate::manifest::*;
 use crate::kgui::*;
-use crate::rendererUV::TriangleBufferUV;
+use crate::rendererUV::{TriangleBufferUV, TextStyle, Align};
+use crate::progress::*;
+use crate::bindings::*;
+
+use serde::{Serialize, Deserialize};
 
 use std::collections::HashMap;
 
@@ -12,51 +16,351 @@ use glutin::event::ElementState;
 use glutin::event::VirtualKeyCode;
 
 
+// how long a rejected placement flashes and shakes for
+const REJECT_SECONDS: f32 = 0.6;
+const SHAKE_DISTANCE: f32 = 0.01;
+
+// the last placement that didnt fit, shown until its age runs out
+pub struct Rejection {
+    pub cell: (i32, i32),
+    pub placement: Placement,
+    pub age: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum GameAction {
+    RotateLeft,
+    RotateRight,
+    Hint,
+    Quit,
+    Help,
+    // the keyboard cursor, so it can be played without a mouse
+    MoveCursor((i32, i32)),
+    PickPalette(i32),
+    // steps through the whole palette, the number keys only reach the first nine
+    NextPalette,
+    PrevPalette,
+    Place,
+    Clear,
+}
+
+// tiles a hint tries before giving up, about a second of searching
+const HINT_TRY_LIMIT: u64 = 1_000_000;
+
 pub struct Game {
     pub level: Level,
+    // hash of the puzzle as it started, the level itself fills up as its played
+    pub puzzle_hash: u64,
     pub place_tile: Tile,
     pub place_idx: i32,
+    // hidden until a cursor key is pressed and again when the mouse goes over the board
+    pub cursor: Option<(i32, i32)>,
+    pub camera: BoardCamera,
+    pub rejection: Option<Rejection>,
+    // the board the last hint search ran on and what it found, so pressing H again doesnt search again
+    hint_search: Option<(Level, Option<Level>)>,
+
+    pub progress: SharedProgress,
+    pub bindings: SharedBindings,
+    // editor playtests dont count towards progress
+    pub record: bool,
+    pub daily: Option<String>,
+    pub solved: bool,
+    pub moves: i32,
+    pub hints: i32,
+
+    // challenge mode shows the clock against par and keeps best times
+    pub timed: bool,
+    pub par: Option<f32>,
+    pub elapsed: f32,
 }
 
 impl Game {
+    pub fn new(level: Level, progress: SharedProgress, bindings: SharedBindings) -> Game {
+        Game {
+            place_tile: level.tile_palette[0],
+            place_idx: 0,
+            cursor: None,
+            camera: BoardCamera::default(),
+            rejection: None,
+            hint_search: None,
+            puzzle_hash: level.hash(),
+            level,
+            progress,
+            bindings,
+            record: false,
+            daily: None,
+            solved: false,
+            moves: 0,
+            hints: 0,
+            timed: false,
+            par: None,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn new_timed(level: Level, par: Option<f32>, progress: SharedProgress, bindings: SharedBindings) -> Game {
+        let mut game = Game::new(level, progress, bindings);
+        game.record = true;
+        game.timed = true;
+        game.par = par;
+        game
+    }
+
+    pub fn new_daily(level: Level, date: String, progress: SharedProgress, bindings: SharedBindings) -> Game {
+        let mut game = Game::new(level, progress, bindings);
+        game.record = true;
+        game.daily = Some(date);
+        game
+    }
+
+    fn check_solved(&mut self) {
+        if self.solved || !self.level.is_solved() {
+            return;
+        }
+        self.solved = true;
+        let seconds = self.elapsed;
+        println!("solved in {:.1}s, {} moves, {} hints", seconds, self.moves, self.hints);
+
+        if !self.record {
+            return;
+        }
+        let mut progress = self.progress.borrow_mut();
+        if let Some(date) = &self.daily {
+            if progress.record_daily(date, seconds) {
+                println!("new best for daily {}", date);
+            }
+        }
+        progress.record_solve(self.puzzle_hash, if self.timed { Some(seconds) } else { None }, self.moves, self.hints);
+        if self.timed {
+            println!("{:?}", Medal::for_time(seconds, self.par));
+        }
+        progress.save();
+    }
+
+    // same rules for the mouse and the cursor, a rejected tile is only reported when asked for
+    fn place(&mut self, x: i32, y: i32, report: bool) {
+        if self.level.get_tile(x, y) == Some(self.place_tile) || self.level.get_locked(x, y) {
+            return;
+        }
+        if self.level.fits(x, y, self.place_tile) {
+            self.level.set_tile(x, y, self.place_tile);
+            self.moves += 1;
+            self.check_solved();
+        } else if report {
+            let placement = self.level.can_place(x, y, self.place_tile);
+            self.rejection = Some(Rejection { cell: (x, y), placement, age: 0.0 });
+        }
+    }
+
+    fn clear(&mut self, x: i32, y: i32) {
+        if !self.level.get_locked(x, y) && self.level.get_tile(x, y).is_some() {
+            self.level.clear_tile(x, y);
+            self.moves += 1;
+        }
+    }
+
+    fn pick_palette(&mut self, idx: i32) {
+        if idx >= 0 && (idx as usize) < self.level.tile_palette.len() {
+            self.place_idx = idx;
+            self.place_tile = self.level.tile_palette[idx as usize];
+        }
+    }
+
+    fn step_palette(&mut self, step: i32) {
+        let n = self.level.tile_palette.len() as i32;
+        if n > 0 {
+            self.pick_palette((self.place_idx + step).rem_euclid(n));
+        }
+    }
 
+    // the first press just shows the cursor in the top left
+    fn move_cursor(&mut self, dx: i32, dy: i32) -> (i32, i32) {
+        let (x, y) = match self.cursor {
+            Some((x, y)) => ((x + dx).max(0).min(self.level.w - 1), (y + dy).max(0).min(self.level.h - 1)),
+            None => (0, 0),
+        };
+        self.cursor = Some((x, y));
+        self.camera.show_cell(&self.level, x, y);
+        (x, y)
+    }
+
+    // Tries to keep the players tiles, if they've gone wrong solve from the locked tiles instead.
+    // Both searches are capped, a big empty board would otherwise hang the game.
+    fn find_solution(&self) -> Option<Level> {
+        if let Ok(Some(solution)) = self.level.solve_limited(HINT_TRY_LIMIT) {
+            return Some(solution);
+        }
+        let mut clues = self.level.clone();
+        for i in 0..clues.w {
+            for j in 0..clues.h {
+                if !clues.get_locked(i, j) {
+                    clues.clear_tile(i, j);
+                }
+            }
+        }
+        clues.solve_limited(HINT_TRY_LIMIT).unwrap_or(None)
+    }
+
+    // Fill in one cell from a solution. The last one found is used again while every tile on the board still agrees with it.
+    fn hint(&mut self) {
+        let reusable = match self.hint_search.as_ref() {
+            Some((_, Some(solution))) => (0..self.level.w).all(|i| (0..self.level.h).all(|j|
+                self.level.get_tile(i, j).map_or(true, |t| solution.get_tile(i, j) == Some(t)))),
+            Some((board, None)) => *board == self.level,
+            None => false,
+        };
+        if !reusable {
+            self.hint_search = Some((self.level.clone(), self.find_solution()));
+        }
+        let solution = match self.hint_search.as_ref().and_then(|(_, solution)| solution.clone()) {
+            Some(solution) => solution,
+            None => {
+                println!("no hint found");
+                return;
+            },
+        };
+
+        for i in 0..self.level.w {
+            for j in 0..self.level.h {
+                if self.level.get_tile(i, j) != solution.get_tile(i, j) {
+                    self.level.set_tile(i, j, solution.get_tile(i, j).unwrap());
+                    self.hints += 1;
+                    self.check_solved();
+                    return;
+                }
+            }
+        }
+    }
 }
 
 impl Scene for Game {
-    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, TriangleBuffer, Option<TriangleBufferUV>) {
-        let mut buf = TriangleBuffer::new(inputs.screen_rect);
-        let mut buf_uv = TriangleBufferUV::new(inputs.screen_rect, ATLAS_W, ATLAS_H);
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>) {
+        let mut buf = TriangleBuffer::new();
+        let mut buf_uv = TriangleBufferUV::new(ATLAS_W, ATLAS_H);
+
+        if !self.solved {
+            self.elapsed += inputs.dt;
+        }
         
-        let click = inputs.events.iter().any(|e| match e {KEvent::MouseLeft(true) => true, _ => false});
-        let clickr = inputs.events.iter().any(|e| match e {KEvent::MouseRight(true) => true, _ => false});
+        let click = inputs.lmb_pressed();
+        let clickr = inputs.rmb_pressed();
 
-        let (maybe_rollover_palette, maybe_rollover_grid) = self.level.frame(&mut buf, &mut buf_uv, inputs.screen_rect, &inputs, Some(self.place_idx));
+        // middle drag or space drag moves the board around, nothing gets placed while its moving
+        let panning = inputs.held_mmb || inputs.held_lmb && inputs.held_keys.contains(&VirtualKeyCode::Space);
+        let camera_busy = self.camera.update(&self.level, inputs.screen_rect, &inputs, panning);
+
+        // a rejected placement shakes the board side to side, dying away
+        if let Some(rejection) = self.rejection.as_mut() {
+            rejection.age += inputs.dt;
+            if rejection.age > REJECT_SECONDS {
+                self.rejection = None;
+            }
+        }
+        let shake = match self.rejection.as_ref() {
+            Some(rejection) => Vec2::new((rejection.age * 60.0).sin() * SHAKE_DISTANCE * (1.0 - rejection.age / REJECT_SECONDS), 0.0),
+            None => Vec2::new(0.0, 0.0),
+        };
+        let mut board = self.camera.layer(&self.level, inputs.screen_rect, inputs.screen_rect, shake);
+
+        let (maybe_rollover_palette, maybe_rollover_grid) = self.level.frame_camera(&mut buf, &mut board, inputs.screen_rect, &inputs, Some(self.place_idx), &self.camera);
+        let maybe_rollover_grid = if camera_busy { None } else { maybe_rollover_grid };
         if let Some(rollover_palette) = maybe_rollover_palette {
             if click || inputs.held_lmb {
-                self.place_tile = self.level.tile_palette[rollover_palette as usize];
-                self.place_idx = rollover_palette;
+                self.pick_palette(rollover_palette);
+            }
+            let steps = inputs.wheel_steps(self.level.palette_tile_rect(inputs.screen_rect, rollover_palette));
+            if steps != 0 {
+                self.pick_palette((self.place_idx + steps).rem_euclid(self.level.tile_palette.len() as i32));
             }
         }
         if let Some((x, y)) = maybe_rollover_grid {
+            let moved = inputs.events.iter().any(|e| matches!(e, KEvent::MouseMotion(_)));
+            if moved {
+                self.cursor = None;
+            }
             if click || inputs.held_lmb {
-                if self.level.can_place(x, y, self.place_tile) && !self.level.get_locked(x, y) {
-                    self.level.set_tile(x, y, self.place_tile);
-                }
-            } else if (clickr || inputs.held_rmb) && !self.level.get_locked(x, y) {
-                self.level.clear_tile(x, y);
+                self.place(x, y, click);
+            } else if clickr || inputs.held_rmb {
+                self.clear(x, y);
             }
         }
 
-        for event in inputs.events {
-            match event {
-                KEvent::Keyboard(VirtualKeyCode::Q, true) => self.place_tile = [self.place_tile[1], self.place_tile[2], self.place_tile[3], self.place_tile[0]],
-                KEvent::Keyboard(VirtualKeyCode::E, true) => self.place_tile = [self.place_tile[3], self.place_tile[0], self.place_tile[1], self.place_tile[2]],
-                KEvent::Keyboard(VirtualKeyCode::Escape, true) => {return (SceneOutcome::Pop(SceneSignal::JustPop), buf, None)},
-                _ => {},
+        if self.solved {
+            buf.draw_rect(inputs.screen_rect, Vec3::new(0.1, 0.4, 0.1), 0.5);
+            let banner = inputs.screen_rect.child(0.2, 0.4, 0.8, 0.2);
+            let summary = format!("solved in {:.1}s\n{} moves, {} hints", self.elapsed, self.moves, self.hints);
+            buf.draw_rect(banner, Vec3::new(0.05, 0.05, 0.05), 19.0);
+            buf_uv.draw_text(banner.dilate(-0.02), &summary, Vec3::new(0.4, 1.0, 0.4), 20.0);
+        }
+
+        if self.timed {
+            // timer bar along the top, fills up as par approaches and goes red once its blown
+            let timer_rect = inputs.screen_rect.child(0.0, 0.0, 1.0, 0.01);
+            let (t, colour) = match self.par {
+                Some(par) if self.elapsed <= par => (self.elapsed / par, Vec3::new(0.9, 0.8, 0.2)),
+                Some(_) => (1.0, Vec3::new(0.8, 0.1, 0.1)),
+                None => ((self.elapsed / 60.0).fract(), Vec3::new(0.6, 0.6, 0.6)),
+            };
+            buf.draw_rect(timer_rect.child(0.0, 0.0, t, 1.0), colour, 10.0);
+            let time = match self.par {
+                Some(par) => format!("{:.1} / {:.1}", self.elapsed, par),
+                None => format!("{:.1}", self.elapsed),
+            };
+            let time_rect = inputs.screen_rect.child(0.7, 0.015, 0.29, 0.03);
+            buf_uv.draw_text_style(time_rect, &time, colour, 10.0, TextStyle { size: time_rect.h, align: Align::Right, wrap: false });
+        }
+
+        for action in actions(&self.bindings.game, &inputs) {
+            match action {
+                GameAction::RotateLeft => self.place_tile = [self.place_tile[1], self.place_tile[2], self.place_tile[3], self.place_tile[0]],
+                GameAction::RotateRight => self.place_tile = [self.place_tile[3], self.place_tile[0], self.place_tile[1], self.place_tile[2]],
+                GameAction::Hint => self.hint(),
+                GameAction::Quit => {return (SceneOutcome::Pop(SceneSignal::JustPop), vec![board, Layer::screen(inputs.screen_rect, buf, None)])},
+                GameAction::Help => {return (SceneOutcome::Push(Box::new(HelpScreen::new("game", &self.bindings.game))), vec![board, Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])},
+                GameAction::MoveCursor((dx, dy)) => {self.move_cursor(dx, dy);},
+                GameAction::PickPalette(idx) => self.pick_palette(idx),
+                GameAction::NextPalette => self.step_palette(1),
+                GameAction::PrevPalette => self.step_palette(-1),
+                GameAction::Place => {
+                    let (x, y) = self.move_cursor(0, 0);
+                    self.place(x, y, true);
+                },
+                GameAction::Clear => {
+                    let (x, y) = self.move_cursor(0, 0);
+                    self.clear(x, y);
+                },
             }
         }
 
-        (SceneOutcome::None, buf, Some(buf_uv))
+        // and both sides of each edge that didnt match flash
+        if let Some(rejection) = self.rejection.as_ref() {
+            let fade = 1.0 - rejection.age / REJECT_SECONDS;
+            let flash = if (rejection.age * 8.0) as i32 % 2 == 0 { 1.0 } else { 0.3 };
+            let colour = Vec3::new(1.0, 0.1, 0.1).with_alpha(flash * fade);
+            let (x, y) = rejection.cell;
+            for c in rejection.placement.conflicts() {
+                let ours = self.level.board_cell_rect(inputs.screen_rect, x, y);
+                let theirs = self.level.board_cell_rect(inputs.screen_rect, c.neighbour.0, c.neighbour.1);
+                board.tris.draw_rect_alpha(edge_rect(ours, c.side), colour, 8.0);
+                board.tris.draw_rect_alpha(edge_rect(theirs, (c.side + 2) % 4), colour, 8.0);
+            }
+        }
+
+        // whatever is hovered, or the cursor when its the keyboard playing
+        if let Some((x, y)) = self.cursor.or(maybe_rollover_grid) {
+            if !self.solved && !self.level.get_locked(x, y) && self.level.get_tile(x, y) != Some(self.place_tile) {
+                self.level.draw_ghost_checked(&mut board.tris, inputs.screen_rect, x, y, self.place_tile);
+            }
+        }
+
+        // the board layer gets zoomed, the outline stays the same width on screen
+        if let Some((x, y)) = self.cursor {
+            let cell = self.level.board_cell_rect(inputs.screen_rect, x, y);
+            board.tris.draw_outline(cell.dilate(0.005 / self.camera.zoom), 0.01 / self.camera.zoom, Vec3::new(1.0, 1.0, 1.0), 5.0);
+        }
+
+        (SceneOutcome::None, vec![board, Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])
     }
     
     fn handle_signal(&mut self, signal: SceneSignal) -> SceneOutcome {
@@ -0,0 +1,243 @@
+// Golden image tests. Scenes are driven through Scene::frame with made up input, the last frame is
+// drawn with the Rasterizer and compared against the PNGs in golden/.
+// After a change that is meant to look different, run with UPDATE_GOLDEN=1 to rewrite them and check the new images in.
+
+use crate::application::*;
+use crate::editor::*;
+use crate::game::*;
+use crate::kgui::*;
+use crate::kimg::*;
+use crate::kmath::*;
+use crate::level::*;
+use crate::level_menu::*;
+use crate::manifest::*;
+use crate::pack::*;
+use crate::progress::*;
+use crate::raster::*;
+use crate::renderer::*;
+use crate::rendererUV::*;
+use crate::bindings::*;
+
+use glutin::event::VirtualKeyCode;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const GOLDEN_DIR: &str = "golden/";
+const W: usize = 480;
+const H: usize = 270;
+// a channel can be off by this much before the pixel counts as different, and this share of pixels can be different
+const CHANNEL_TOLERANCE: i32 = 8;
+const PIXEL_TOLERANCE: f32 = 0.002;
+
+// Builds up the frames one at a time, positions are in screen space, 0..16/9 across and 0..1 down
+struct Script {
+    frames: Vec<FrameInputState>,
+    current: FrameInputState,
+}
+
+impl Script {
+    fn new() -> Script {
+        let mut current = FrameInputState::new(Rect::new(0.0, 0.0, W as f32 / H as f32, 1.0));
+        current.dt = 1.0 / 60.0;
+        Script { frames: Vec::new(), current }
+    }
+
+    fn frame(mut self, events: Vec<KEvent>) -> Script {
+        let mut frame = self.current.clone();
+        frame.events = events;
+        self.frames.push(frame);
+        self
+    }
+
+    fn idle(self) -> Script {
+        self.frame(Vec::new())
+    }
+
+    fn mouse(mut self, x: f32, y: f32) -> Script {
+        self.current.mouse_pos = Vec2::new(x, y);
+        self.frame(vec![KEvent::MouseMotion(Vec2::new(x, y))])
+    }
+
+    fn click(mut self) -> Script {
+        self.current.held_lmb = true;
+        self.current.lmb_down_pos = Some(self.current.mouse_pos);
+        self = self.frame(vec![KEvent::MouseLeft(true)]);
+        self.current.held_lmb = false;
+        self = self.frame(vec![KEvent::MouseLeft(false)]);
+        self.current.lmb_down_pos = None;
+        self
+    }
+
+    fn key(mut self, key: VirtualKeyCode) -> Script {
+        self.current.held_keys.insert(key);
+        self = self.frame(vec![KEvent::Keyboard(key, true)]);
+        self.current.held_keys.remove(&key);
+        self.frame(vec![KEvent::Keyboard(key, false)])
+    }
+}
+
+fn run(stack: &mut SceneStack, script: Script) -> ImageBufferA {
+    let mut rasterizer = Rasterizer::new(W, H, ImageBufferA::new_from_file(ATLAS_PATH));
+    for inputs in script.frames.into_iter() {
+        let layers = stack.frame(inputs);
+        rasterizer.clear();
+        for layer in layers.iter() {
+            rasterizer.draw(layer);
+        }
+    }
+    rasterizer.image
+}
+
+fn check(name: &str, image: ImageBufferA) {
+    let path = format!("{}{}.png", GOLDEN_DIR, name);
+    if std::env::var("UPDATE_GOLDEN").is_ok() {
+        std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+        image.dump_to_file(&path);
+        return;
+    }
+    let actual_path = format!("{}{}.actual.png", GOLDEN_DIR, name);
+    if !std::path::Path::new(&path).exists() {
+        image.dump_to_file(&actual_path);
+        panic!("no golden image {}, the output is in {}, run with UPDATE_GOLDEN=1 to accept it", path, actual_path);
+    }
+
+    let golden = ImageBufferA::new_from_file(&path);
+    assert_eq!((golden.w, golden.h), (image.w, image.h), "{} is a different size", path);
+    let different = golden.pixels.iter().zip(image.pixels.iter()).filter(|(a, b)| {
+        let diff = |x: u8, y: u8| (x as i32 - y as i32).abs() > CHANNEL_TOLERANCE;
+        diff(a.0, b.0) || diff(a.1, b.1) || diff(a.2, b.2) || diff(a.3, b.3)
+    }).count();
+    if different as f32 > PIXEL_TOLERANCE * (W * H) as f32 {
+        image.dump_to_file(&actual_path);
+        panic!("{} pixels differ from {}, the output is in {}", different, path, actual_path);
+    }
+}
+
+fn fresh_progress() -> SharedProgress {
+    Rc::new(RefCell::new(Progress::default()))
+}
+
+// not Bindings::load, that would read and write the bindings file
+fn default_bindings() -> SharedBindings {
+    Rc::new(Bindings::defaults())
+}
+
+#[test]
+fn editor_empty() {
+    let mut editor = Editor::new(fresh_progress(), default_bindings());
+    editor.no_disk = true;
+    let mut stack = SceneStack::new(Box::new(editor));
+    check("editor_empty", run(&mut stack, Script::new().idle()));
+}
+
+#[test]
+fn editor_edits() {
+    let mut editor = Editor::new(fresh_progress(), default_bindings());
+    editor.no_disk = true;
+    editor.set_level(LevelMetadata::new(Level::generate(4, 3, 3, 7)));
+    let mut stack = SceneStack::new(Box::new(editor));
+    let script = Script::new()
+        // green in the top wedge, rotated, onto the board
+        .mouse(1.64, 0.52).click()
+        .key(VirtualKeyCode::E)
+        .mouse(0.89, 0.5).click()
+        // a column wider, then onto the palette
+        .mouse(0.22, 0.5).click()
+        .mouse(0.39, 0.36).click()
+        .mouse(-1.0, -1.0)
+        .idle();
+    check("editor_edits", run(&mut stack, script));
+}
+
+#[test]
+fn game_place() {
+    let mut stack = SceneStack::new(Box::new(Game::new(Level::generate(4, 4, 3, 3), fresh_progress(), default_bindings())));
+    let script = Script::new()
+        // third palette tile rotated, then a hint fills a cell
+        .mouse(0.17, 0.2).click()
+        .key(VirtualKeyCode::Q)
+        .key(VirtualKeyCode::H)
+        .idle();
+    check("game_place", run(&mut stack, script));
+}
+
+#[test]
+fn game_keyboard() {
+    let mut stack = SceneStack::new(Box::new(Game::new(Level::generate(4, 4, 3, 3), fresh_progress(), default_bindings())));
+    let script = Script::new()
+        // no mouse at all, second palette tile into the cell right of the top left, then the cursor moves on
+        .key(VirtualKeyCode::Key2)
+        .key(VirtualKeyCode::Right)
+        .key(VirtualKeyCode::Right)
+        .key(VirtualKeyCode::E)
+        .key(VirtualKeyCode::Return)
+        .key(VirtualKeyCode::S)
+        .idle();
+    check("game_keyboard", run(&mut stack, script));
+}
+
+#[test]
+fn game_reject() {
+    let mut stack = SceneStack::new(Box::new(Game::new(Level::generate(4, 4, 3, 3), fresh_progress(), default_bindings())));
+    let script = Script::new()
+        // the same tile under the one placed doesnt fit, so its edges flash
+        .key(VirtualKeyCode::Key2)
+        .key(VirtualKeyCode::Right)
+        .key(VirtualKeyCode::Right)
+        .key(VirtualKeyCode::E)
+        .key(VirtualKeyCode::Return)
+        .key(VirtualKeyCode::S)
+        .key(VirtualKeyCode::Return)
+        .idle();
+    check("game_reject", run(&mut stack, script));
+}
+
+#[test]
+fn game_zoom() {
+    let mut stack = SceneStack::new(Box::new(Game::new(Level::generate(16, 16, 3, 5), fresh_progress(), default_bindings())));
+    let script = Script::new()
+        // zoomed in past where the minimap shows, then a tile placed under the mouse
+        .mouse(1.1, 0.6)
+        .frame(vec![KEvent::MouseWheel(4.0)])
+        .mouse(0.17, 0.05).click()
+        .mouse(1.1, 0.6).click()
+        .idle();
+    check("game_zoom", run(&mut stack, script));
+}
+
+#[test]
+fn level_menu_pages() {
+    let levels = (0..20).map(|i| LevelMetadata::new(Level::generate(3 + i % 3, 3, 2 + i % 2, i as u32))).collect();
+    let pack = LevelPack::new(PackMetadata::new(String::from("golden"), false), levels);
+    let mut stack = SceneStack::new(Box::new(LevelMenu::new(pack.clone(), fresh_progress(), default_bindings())));
+    let script = Script::new()
+        .key(VirtualKeyCode::D)
+        .key(VirtualKeyCode::S)
+        .idle();
+    check("level_menu", run(&mut stack, script));
+
+    let mut stack = SceneStack::new(Box::new(LevelMenu::new(pack, fresh_progress(), default_bindings())));
+    let script = Script::new()
+        .key(VirtualKeyCode::PageDown)
+        .idle();
+    check("level_menu_page_2", run(&mut stack, script));
+}
+
+#[test]
+fn text_layout() {
+    let screen = Rect::new(0.0, 0.0, W as f32 / H as f32, 1.0);
+    let mut buf_uv = TriangleBufferUV::new(ATLAS_W, ATLAS_H);
+    buf_uv.draw_text(screen.child(0.0, 0.0, 1.0, 0.2), "Level 12: Tape!", Vec3::new(1.0, 1.0, 1.0), 1.0);
+    let para = "the quick brown fox jumps over the lazy dog, 0123456789 ~{[(<?>)]}";
+    for (i, align) in [Align::Left, Align::Centre, Align::Right].into_iter().enumerate() {
+        let rect = screen.child(i as f32 / 3.0, 0.3, 1.0 / 3.0, 0.4).dilate(-0.02);
+        buf_uv.draw_text_style(rect, para, COLOURS[i], 1.0, TextStyle { size: 0.05, align, wrap: true });
+    }
+    // scaled down, and cut off at the bottom of the rect
+    buf_uv.draw_text_style(screen.child(0.0, 0.75, 1.0, 0.2), &para.repeat(8), Vec3::new(0.5, 0.5, 0.5), 1.0, TextStyle::new(0.025));
+
+    let mut rasterizer = Rasterizer::new(W, H, ImageBufferA::new_from_file(ATLAS_PATH));
+    rasterizer.draw(&Layer::screen(screen, TriangleBuffer::new(), Some(buf_uv)));
+    check("text_layout", rasterizer.image);
+}
@@ -1,6 +1,12 @@
 use crate::kmath::*;
+use crate::manifest::*;
+use crate::renderer::*;
+use crate::rendererUV::*;
 
 use std::collections::HashSet;
+use std::time::Instant;
+
+use serde::{Serialize, Deserialize};
 
 use glutin::event::VirtualKeyCode;
 
@@ -11,18 +17,50 @@ use glutin::event::WindowEvent::KeyboardInput;
 use glutin::event::WindowEvent::MouseInput;
 use glutin::event::WindowEvent::CursorMoved;
 use glutin::event::WindowEvent::Resized;
+use glutin::event::WindowEvent::ReceivedCharacter;
+use glutin::event::WindowEvent::MouseWheel;
+use glutin::event::WindowEvent::ModifiersChanged;
+use glutin::event::MouseScrollDelta;
+
+// second press has to come this soon after the first and this close to it to be a double click
+const DOUBLE_CLICK_TIME: f32 = 0.4;
+const DOUBLE_CLICK_DISTANCE: f32 = 0.01;
+// how far the mouse goes with the left button down before its a drag and not a click
+const DRAG_DISTANCE: f32 = 0.01;
+// trackpads give pixels, this many is about one notch of a wheel
+const PIXELS_PER_LINE: f32 = 30.0;
 
 // Do I dare???
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub enum KEvent {
     Keyboard(VirtualKeyCode, bool),
     MouseLeft(bool),
     MouseRight(bool),
     MouseMiddle(bool),
     MouseMotion(Vec2),
+    Char(char),
+    // whole lines, up is positive
+    MouseWheel(f32),
+    // comes after the second MouseLeft(true)
+    DoubleClick,
+    // The origin rect is the patch round the press the mouse had to leave for it to be a drag,
+    // check where a drag came from with rect.contains(origin.centroid()).
+    DragStart(Rect),
+    // origin and where the mouse is now
+    DragUpdate(Rect, Vec2),
+    // origin and where it was let go
+    DragEnd(Rect, Vec2),
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
 }
 
-#[derive(Clone)]
+// serializable so replay.rs can record and play them back
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FrameInputState {
     pub screen_rect: Rect,
     pub mouse_pos: Vec2,
@@ -31,12 +69,108 @@ pub struct FrameInputState {
     pub held_rmb: bool,
     pub held_mmb: bool,
     pub events: Vec<KEvent>,
+    pub dt: f32,
+    // where the left button went down, kept through the frame its let go on so widgets can tell whose press it was
+    #[serde(default)]
+    pub lmb_down_pos: Option<Vec2>,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+    // where the drag in progress started, kept through the frame it ends on like lmb_down_pos
+    #[serde(default)]
+    pub drag_origin: Option<Vec2>,
+}
+
+impl FrameInputState {
+    // a frame where nothing happens and the mouse is off screen
+    pub fn new(screen_rect: Rect) -> FrameInputState {
+        FrameInputState {
+            screen_rect,
+            mouse_pos: Vec2::new(-1.0, -1.0),
+            held_keys: HashSet::new(),
+            held_lmb: false,
+            held_rmb: false,
+            held_mmb: false,
+            events: Vec::new(),
+            dt: 0.0,
+            lmb_down_pos: None,
+            modifiers: Modifiers::default(),
+            drag_origin: None,
+        }
+    }
+
+    pub fn lmb_pressed(&self) -> bool {
+        self.events.iter().any(|e| matches!(e, KEvent::MouseLeft(true)))
+    }
+    pub fn lmb_released(&self) -> bool {
+        self.events.iter().any(|e| matches!(e, KEvent::MouseLeft(false)))
+    }
+    pub fn rmb_pressed(&self) -> bool {
+        self.events.iter().any(|e| matches!(e, KEvent::MouseRight(true)))
+    }
+    pub fn mmb_pressed(&self) -> bool {
+        self.events.iter().any(|e| matches!(e, KEvent::MouseMiddle(true)))
+    }
+    pub fn key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.events.iter().any(|e| matches!(e, KEvent::Keyboard(k, true) if *k == key))
+    }
+
+    // the left button went down in rect and is still down, or came up this frame
+    pub fn lmb_down_in(&self, rect: Rect) -> bool {
+        self.lmb_down_pos.map_or(false, |p| rect.contains(p))
+    }
+
+    // pressed and let go without leaving rect, what a button counts as a click
+    pub fn clicked(&self, rect: Rect) -> bool {
+        self.lmb_released() && self.lmb_down_in(rect) && rect.contains(self.mouse_pos)
+    }
+
+    // lines scrolled this frame, up is positive
+    pub fn wheel(&self) -> f32 {
+        self.events.iter().map(|e| match e { KEvent::MouseWheel(lines) => *lines, _ => 0.0 }).sum()
+    }
+
+    // whole notches scrolled over rect, down is positive like rows and pages are
+    pub fn wheel_steps(&self, rect: Rect) -> i32 {
+        if rect.contains(self.mouse_pos) { -self.wheel().round() as i32 } else { 0 }
+    }
+
+    pub fn double_clicked(&self) -> bool {
+        self.events.iter().any(|e| matches!(e, KEvent::DoubleClick))
+    }
+
+    // the origin of a drag that started this frame
+    pub fn drag_started(&self) -> Option<Rect> {
+        self.events.iter().find_map(|e| match e { KEvent::DragStart(origin) => Some(*origin), _ => None })
+    }
+
+    // where the drag got to by the end of this frame, None if it didnt move
+    pub fn drag_moved(&self) -> Option<(Rect, Vec2)> {
+        self.events.iter().rev().find_map(|e| match e { KEvent::DragUpdate(origin, pos) => Some((*origin, *pos)), _ => None })
+    }
+
+    // origin and where it was let go, the frame a drag finishes
+    pub fn drag_ended(&self) -> Option<(Rect, Vec2)> {
+        self.events.iter().find_map(|e| match e { KEvent::DragEnd(origin, pos) => Some((*origin, *pos)), _ => None })
+    }
+}
+
+fn drag_origin_rect(origin: Vec2) -> Rect {
+    Rect::new_centered(origin.x, origin.y, DRAG_DISTANCE * 2.0, DRAG_DISTANCE * 2.0)
+}
+
+// the last press, to tell if the next one is a double click
+struct LastClick {
+    at: Instant,
+    pos: Vec2,
 }
 
 pub struct EventAggregator {
     xres: f32,
     yres: f32,
     current: FrameInputState,
+    last_click: Option<LastClick>,
+    // trackpads scroll a fraction of a line at a time, this adds them up until theres a whole one
+    wheel_remainder: f32,
 }
 
 impl EventAggregator {
@@ -44,15 +178,9 @@ impl EventAggregator {
         EventAggregator { 
             xres, 
             yres, 
-            current: FrameInputState { 
-                screen_rect: Rect::new(0.0, 0.0, xres/yres, 1.0, ), 
-                mouse_pos: Vec2::new(0.0, 0.0), 
-                held_keys: HashSet::new(),
-                held_lmb: false, 
-                held_rmb: false, 
-                held_mmb: false, 
-                events: Vec::new(),
-            }
+            current: FrameInputState::new(Rect::new(0.0, 0.0, xres/yres, 1.0)),
+            last_click: None,
+            wheel_remainder: 0.0,
         }
     }
 
@@ -66,6 +194,13 @@ impl EventAggregator {
                     ..},
                 ..} => {
                     self.current.events.push(KEvent::Keyboard(*virtual_code, *state == ElementState::Pressed));
+                    // pasted text comes through as typed characters, so replays get it without the clipboard
+                    if *virtual_code == VirtualKeyCode::V && *state == ElementState::Pressed && self.current.modifiers.ctrl {
+                        match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+                            Ok(text) => self.current.events.extend(text.chars().map(KEvent::Char)),
+                            Err(e) => println!("couldn't paste: {}", e),
+                        }
+                    }
                     if *state == ElementState::Pressed {
                         self.current.held_keys.insert(*virtual_code);
                     } else {
@@ -78,8 +213,22 @@ impl EventAggregator {
                     self.current.events.push(KEvent::MouseLeft(*state == ElementState::Pressed));
                     if *state == ElementState::Pressed {
                         self.current.held_lmb = true;
+                        self.current.lmb_down_pos = Some(self.current.mouse_pos);
+                        let pos = self.current.mouse_pos;
+                        let double = self.last_click.as_ref().map_or(false, |last|
+                            last.at.elapsed().as_secs_f32() < DOUBLE_CLICK_TIME && (pos - last.pos).magnitude() < DOUBLE_CLICK_DISTANCE);
+                        if double {
+                            self.current.events.push(KEvent::DoubleClick);
+                            // a third click starts again rather than being another double
+                            self.last_click = None;
+                        } else {
+                            self.last_click = Some(LastClick { at: Instant::now(), pos });
+                        }
                     } else {
                         self.current.held_lmb = false;
+                        if let Some(origin) = self.current.drag_origin {
+                            self.current.events.push(KEvent::DragEnd(drag_origin_rect(origin), self.current.mouse_pos));
+                        }
                     }
                 },
                 MouseInput { button: glutin::event::MouseButton::Middle, state, ..} => {
@@ -109,6 +258,44 @@ impl EventAggregator {
                     let new_cursor_pos = Vec2::new(pos.x as f32 / self.yres, pos.y as f32 / self.yres);
                     self.current.events.push(KEvent::MouseMotion(new_cursor_pos - old_cursor_pos));
                     self.current.mouse_pos = new_cursor_pos;
+
+                    if self.current.held_lmb {
+                        match (self.current.drag_origin, self.current.lmb_down_pos) {
+                            (Some(origin), _) => self.current.events.push(KEvent::DragUpdate(drag_origin_rect(origin), new_cursor_pos)),
+                            (None, Some(origin)) if (new_cursor_pos - origin).magnitude() > DRAG_DISTANCE => {
+                                self.current.drag_origin = Some(origin);
+                                self.current.events.push(KEvent::DragStart(drag_origin_rect(origin)));
+                                self.current.events.push(KEvent::DragUpdate(drag_origin_rect(origin), new_cursor_pos));
+                            },
+                            _ => {},
+                        }
+                    }
+                },
+
+                MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(p) => p.y as f32 / PIXELS_PER_LINE,
+                    };
+                    // turning back the other way starts again from nothing
+                    if lines * self.wheel_remainder < 0.0 {
+                        self.wheel_remainder = 0.0;
+                    }
+                    let total = self.wheel_remainder + lines;
+                    let whole = total.trunc();
+                    self.wheel_remainder = total - whole;
+                    if whole != 0.0 {
+                        self.current.events.push(KEvent::MouseWheel(whole));
+                    }
+                },
+
+                ModifiersChanged(state) => {
+                    self.current.modifiers = Modifiers { ctrl: state.ctrl(), shift: state.shift(), alt: state.alt() };
+                },
+
+                // Typed text, for text fields
+                ReceivedCharacter(c) => {
+                    self.current.events.push(KEvent::Char(*c));
                 },
 
                 // Resize
@@ -126,6 +313,10 @@ impl EventAggregator {
             Event::MainEventsCleared => {
                 let state = self.current.clone();
                 self.current.events = Vec::new();
+                if !self.current.held_lmb {
+                    self.current.lmb_down_pos = None;
+                    self.current.drag_origin = None;
+                }
                 return Some(state);
             },
             _ => {},
@@ -133,4 +324,240 @@ impl EventAggregator {
 
         None
     }
-}
\ No newline at end of file
+}
+// Immediate mode widgets. Nothing is kept between frames, whatever a widget changes lives with the caller
+// and press and release edges come from FrameInputState. Scenes make one of these per frame, draw through it,
+// and finish() hands back the buffers.
+
+pub const TOOLTIP_DEPTH: f32 = 50.0;
+
+const WIDGET_COLOUR: Vec3 = Vec3::new(0.1, 0.1, 0.1);
+const HOVER_COLOUR: Vec3 = Vec3::new(0.18, 0.18, 0.18);
+const PRESSED_COLOUR: Vec3 = Vec3::new(0.05, 0.05, 0.05);
+const TEXT_COLOUR: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+
+pub enum ButtonAppearance<'a> {
+    Colour(Vec3),
+    Sprite(i32),
+    Text(&'a str),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextEdit {
+    None,
+    Changed,
+    Submitted,
+    Cancelled,
+}
+
+pub struct Gui<'a> {
+    pub inputs: &'a FrameInputState,
+    pub buf: TriangleBuffer,
+    pub buf_uv: TriangleBufferUV,
+    // widgets draw their background here and whats on them just above
+    pub depth: f32,
+    tooltip: Option<String>,
+}
+
+impl<'a> Gui<'a> {
+    pub fn new(inputs: &'a FrameInputState) -> Gui<'a> {
+        Gui {
+            inputs,
+            buf: TriangleBuffer::new(),
+            buf_uv: TriangleBufferUV::new(ATLAS_W, ATLAS_H),
+            depth: 5.0,
+            tooltip: None,
+        }
+    }
+
+    pub fn hovered(&self, rect: Rect) -> bool {
+        rect.contains(self.inputs.mouse_pos)
+    }
+
+    // held down on it, sliding off and back on again still counts
+    pub fn pressed(&self, rect: Rect) -> bool {
+        self.inputs.held_lmb && self.inputs.lmb_down_in(rect) && self.hovered(rect)
+    }
+
+    fn background(&mut self, rect: Rect) {
+        let colour = if self.pressed(rect) {
+            PRESSED_COLOUR
+        } else if self.hovered(rect) && !self.inputs.held_lmb {
+            HOVER_COLOUR
+        } else {
+            WIDGET_COLOUR
+        };
+        self.buf.draw_rect(rect, colour, self.depth);
+    }
+
+    fn appearance(&mut self, rect: Rect, appearance: ButtonAppearance) {
+        // pressed things sink in a little
+        let rect = if self.pressed(rect) { rect.dilate(-0.003) } else { rect };
+        match appearance {
+            ButtonAppearance::Colour(colour) => self.buf.draw_rect(rect.dilate(-0.01), colour, self.depth + 1.0),
+            ButtonAppearance::Sprite(sprite) => self.buf_uv.draw_sprite(rect.fit_center_square(), sprite, self.depth + 1.0),
+            ButtonAppearance::Text(text) => self.buf_uv.draw_text(rect.dilate(-0.01), text, TEXT_COLOUR, self.depth + 1.0),
+        }
+    }
+
+    pub fn button(&mut self, rect: Rect, appearance: ButtonAppearance) -> bool {
+        self.background(rect);
+        self.appearance(rect, appearance);
+        self.inputs.clicked(rect)
+    }
+
+    // a button with an outline while its on, true the frame it flips
+    pub fn toggle(&mut self, rect: Rect, on: &mut bool, appearance: ButtonAppearance) -> bool {
+        if *on {
+            self.buf.draw_rect(rect.dilate(0.005), TEXT_COLOUR, self.depth - 0.5);
+        }
+        let clicked = self.button(rect, appearance);
+        if clicked {
+            *on = !*on;
+        }
+        clicked
+    }
+
+    // drag anywhere on it, the value follows the mouse across
+    pub fn slider(&mut self, rect: Rect, value: &mut f32, min: f32, max: f32) -> bool {
+        let old = *value;
+        let track = rect.child(0.05, 0.45, 0.9, 0.1);
+        if self.inputs.held_lmb && self.inputs.lmb_down_in(rect) {
+            let t = ((self.inputs.mouse_pos.x - track.x) / track.w).max(0.0).min(1.0);
+            *value = min + t * (max - min);
+        }
+        self.background(rect);
+        let t = if max > min { ((*value - min) / (max - min)).max(0.0).min(1.0) } else { 0.0 };
+        self.buf.draw_rect(track, HOVER_COLOUR, self.depth + 1.0);
+        let knob_w = rect.h * 0.3;
+        let knob = Rect::new(track.x + t * track.w - knob_w / 2.0, rect.y + rect.h * 0.15, knob_w, rect.h * 0.7);
+        self.buf.draw_rect(knob, TEXT_COLOUR, self.depth + 2.0);
+        *value != old
+    }
+
+    // - value +, clamped to min..=max
+    pub fn spinner(&mut self, rect: Rect, value: &mut i32, min: i32, max: i32) -> bool {
+        let old = *value;
+        let side = (rect.h).min(rect.w * 0.3);
+        let minus_rect = Rect::new(rect.x, rect.y, side, rect.h);
+        let plus_rect = Rect::new(rect.right() - side, rect.y, side, rect.h);
+        let value_rect = Rect::new(minus_rect.right(), rect.y, rect.w - 2.0 * side, rect.h);
+        if self.button(minus_rect, ButtonAppearance::Text("-")) {
+            *value -= 1;
+        }
+        if self.button(plus_rect, ButtonAppearance::Text("+")) {
+            *value += 1;
+        }
+        // wheel up counts up
+        *value -= self.inputs.wheel_steps(rect);
+        *value = (*value).max(min).min(max);
+        self.buf.draw_rect(value_rect, PRESSED_COLOUR, self.depth);
+        self.buf_uv.draw_text(value_rect.dilate(-0.01), &value.to_string(), TEXT_COLOUR, self.depth + 1.0);
+        *value != old
+    }
+
+    // Clicking it focuses it and clicking anywhere else takes the focus away. While focused it takes typed characters that
+    // accepts lets through, Return submits and Escape cancels.
+    pub fn text_input(&mut self, rect: Rect, text: &mut String, focused: &mut bool, accepts: impl Fn(char) -> bool) -> TextEdit {
+        if self.inputs.lmb_pressed() {
+            *focused = self.hovered(rect);
+        }
+        let mut edit = TextEdit::None;
+        if *focused {
+            for e in self.inputs.events.iter() {
+                match e {
+                    KEvent::Char(c) if accepts(*c) => {
+                        text.push(*c);
+                        edit = TextEdit::Changed;
+                    },
+                    KEvent::Keyboard(VirtualKeyCode::Back, true) => {
+                        text.pop();
+                        edit = TextEdit::Changed;
+                    },
+                    KEvent::Keyboard(VirtualKeyCode::Return, true) => return TextEdit::Submitted,
+                    KEvent::Keyboard(VirtualKeyCode::Escape, true) => {
+                        *focused = false;
+                        return TextEdit::Cancelled;
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        self.buf.draw_rect(rect, if *focused { HOVER_COLOUR } else { WIDGET_COLOUR }, self.depth);
+        // the end of it and a caret, whatever doesnt fit scrolls off the left
+        let inner = rect.dilate(-0.01);
+        let size = inner.h;
+        let fits = ((inner.w / (size * GLYPH_ASPECT)) as usize).max(2) - 1;
+        let chars: Vec<char> = text.chars().collect();
+        let mut shown: String = chars[chars.len().saturating_sub(fits)..].iter().collect();
+        if *focused {
+            shown.push('_');
+        }
+        self.buf_uv.draw_text_style(inner, &shown, TEXT_COLOUR, self.depth + 1.0, TextStyle { size, align: Align::Left, wrap: false });
+        edit
+    }
+
+    // One row per item starting from scroll, with a bar to drag down the side when they dont all fit.
+    // Returns the item clicked on.
+    pub fn scroll_list(&mut self, rect: Rect, items: &[String], row_h: f32, scroll: &mut usize, selected: Option<usize>) -> Option<usize> {
+        let visible = ((rect.h / row_h).floor() as usize).max(1);
+        let max_scroll = items.len().saturating_sub(visible);
+        let bar_w = if max_scroll > 0 { row_h * 0.5 } else { 0.0 };
+        let bar_rect = Rect::new(rect.right() - bar_w, rect.y, bar_w, rect.h);
+        if max_scroll > 0 && self.inputs.held_lmb && self.inputs.lmb_down_in(bar_rect) {
+            let t = ((self.inputs.mouse_pos.y - rect.y) / rect.h).max(0.0).min(1.0);
+            *scroll = (t * max_scroll as f32).round() as usize;
+        }
+        let steps = self.inputs.wheel_steps(rect);
+        *scroll = (*scroll as i32 + steps).max(0) as usize;
+        *scroll = (*scroll).min(max_scroll);
+
+        self.buf.draw_rect(rect, PRESSED_COLOUR, self.depth - 0.5);
+        let mut clicked = None;
+        for (row, idx) in (*scroll..items.len()).take(visible).enumerate() {
+            let row_rect = Rect::new(rect.x, rect.y + row as f32 * row_h, rect.w - bar_w, row_h);
+            if selected == Some(idx) {
+                self.buf.draw_rect(row_rect, HOVER_COLOUR, self.depth);
+            } else if self.hovered(row_rect) {
+                self.background(row_rect);
+            }
+            let style = TextStyle { size: row_h * 0.8, align: Align::Left, wrap: false };
+            self.buf_uv.draw_text_style(row_rect.dilate(-row_h * 0.1), &items[idx], TEXT_COLOUR, self.depth + 1.0, style);
+            if self.inputs.clicked(row_rect) {
+                clicked = Some(idx);
+            }
+        }
+        if max_scroll > 0 {
+            self.buf.draw_rect(bar_rect, WIDGET_COLOUR, self.depth);
+            let thumb_h = rect.h * visible as f32 / items.len() as f32;
+            let thumb_y = rect.y + (rect.h - thumb_h) * *scroll as f32 / max_scroll as f32;
+            self.buf.draw_rect(Rect::new(bar_rect.x, thumb_y, bar_w, thumb_h), HOVER_COLOUR, self.depth + 1.0);
+        }
+        clicked
+    }
+
+    // shown by the mouse while its over rect, drawn last over everything
+    pub fn tooltip(&mut self, rect: Rect, text: &str) {
+        if self.hovered(rect) && !self.inputs.held_lmb {
+            self.tooltip = Some(text.to_string());
+        }
+    }
+
+    pub fn finish(mut self) -> (TriangleBuffer, TriangleBufferUV) {
+        if let Some(text) = self.tooltip.take() {
+            let size = 0.03;
+            let w = text.chars().count() as f32 * size * GLYPH_ASPECT + 0.02;
+            let h = size + 0.02;
+            let screen = self.inputs.screen_rect;
+            let mouse = self.inputs.mouse_pos;
+            // below and right of the mouse unless that goes off screen
+            let x = if mouse.x + 0.02 + w > screen.right() { mouse.x - w } else { mouse.x + 0.02 };
+            let y = if mouse.y + 0.03 + h > screen.bot() { mouse.y - h } else { mouse.y + 0.03 };
+            let rect = Rect::new(x, y, w, h);
+            self.buf.draw_rect(rect, Vec3::new(0.0, 0.0, 0.0), TOOLTIP_DEPTH);
+            self.buf_uv.draw_text(rect.dilate(-0.01), &text, TEXT_COLOUR, TOOLTIP_DEPTH + 1.0);
+        }
+        (self.buf, self.buf_uv)
+    }
+}
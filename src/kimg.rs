@@ -105,7 +105,14 @@ impl ImageBufferA {
     }
     
     pub fn new_from_file(path_str: &str) -> ImageBufferA {
-        let decoder = png::Decoder::new(File::open(path_str).unwrap());
+        ImageBufferA::new_from_png(File::open(path_str).unwrap())
+    }
+    // for images baked into the binary with include_bytes
+    pub fn new_from_bytes(png_bytes: &[u8]) -> ImageBufferA {
+        ImageBufferA::new_from_png(png_bytes)
+    }
+    fn new_from_png<R: std::io::Read>(png: R) -> ImageBufferA {
+        let decoder = png::Decoder::new(png);
         let mut reader = decoder.read_info().unwrap();
         // Allocate the output buffer.
         let mut buf = vec![0; reader.output_buffer_size()];
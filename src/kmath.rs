@@ -1,12 +1,13 @@
+use serde::{Serialize, Deserialize};
 
 /***************************************************
  * RNG
  ***************************************************/
 
 pub fn khash(mut state: u32) -> u32 {
-    state = (state ^ 2747636419) * 2654435769;
-    state = (state ^ (state >> 16)) * 2654435769;
-    state = (state ^ (state >> 16)) * 2654435769;
+    state = (state ^ 2747636419).wrapping_mul(2654435769);
+    state = (state ^ (state >> 16)).wrapping_mul(2654435769);
+    state = (state ^ (state >> 16)).wrapping_mul(2654435769);
     state
 }
 
@@ -18,7 +19,7 @@ pub fn krand(seed: u32) -> f32 {
  * Vec
  ***************************************************/
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -114,6 +115,7 @@ impl Vec3 {
     pub fn rotate_about_vec3(&self, axis: Vec3, theta: f32) -> Vec3 {
         *self*theta.cos() + (axis.cross(*self)*theta.sin()) + axis * (axis.dot(*self)*(1.0 - theta.cos()))
     }
+    pub fn with_alpha(&self, a: f32) -> Vec4 { Vec4::new(self.x, self.y, self.z, a) }
 }
 
 impl std::ops::Sub<Vec3> for Vec3 {
@@ -173,6 +175,37 @@ impl std::ops::AddAssign for Vec3 {
     }
 }
 
+// only used for colours with alpha
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec4 {
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Vec4 { Vec4{x, y, z, w} }
+    pub fn mul_scalar(&self, scalar: f32) -> Vec4 { Vec4::new(self.x * scalar, self.y * scalar, self.z * scalar, self.w * scalar) }
+    pub fn lerp(&self, other: Vec4, t: f32) -> Vec4 { *self * (1.0 - t) + other * t }
+}
+
+impl std::ops::Add<Vec4> for Vec4 {
+    type Output = Vec4;
+
+    fn add(self, _rhs: Vec4) -> Vec4 {
+        Vec4 { x: self.x + _rhs.x, y: self.y + _rhs.y, z: self.z + _rhs.z, w: self.w + _rhs.w }
+    }
+}
+
+impl std::ops::Mul<f32> for Vec4 {
+    type Output = Vec4;
+
+    fn mul(self, _rhs: f32) -> Vec4 {
+        self.mul_scalar(_rhs)
+    }
+}
+
 impl std::fmt::Display for Vec3 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let decimals = f.precision().unwrap_or(2);
@@ -186,7 +219,7 @@ impl std::fmt::Display for Vec3 {
  * Shapes
  ***************************************************/
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Rect {
     pub x: f32,
     pub y: f32,
@@ -293,6 +326,43 @@ impl Rect {
     }
 }
 
+// What part of screen space fills the window. Everything is drawn in screen units (the screen rect is
+// aspect ratio wide and 1 high) and this turns it into GL clip space, on the GPU as the projection
+// uniform in basic.vert and uv.vert and on the CPU in raster.rs. A view smaller than the screen zooms in,
+// moving it pans or shakes.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub view: Rect,
+}
+
+impl Camera {
+    pub fn new(view: Rect) -> Camera {
+        Camera { view }
+    }
+
+    // Column major like glUniformMatrix4fv wants it. y goes down the screen so its flipped, and
+    // z is depth with bigger in front, 1 - 0.001 * depth ends up in the depth buffer
+    pub fn projection(&self) -> [f32; 16] {
+        let v = self.view;
+        [
+            2.0 / v.w, 0.0, 0.0, 0.0,
+            0.0, -2.0 / v.h, 0.0, 0.0,
+            0.0, 0.0, -0.001, 0.0,
+            -1.0 - 2.0 * v.x / v.w, 1.0 + 2.0 * v.y / v.h, 1.0, 1.0,
+        ]
+    }
+
+    // what the projection does but to 0..1 across and down the window, with z the value the depth test compares
+    pub fn to_viewport(&self, p: Vec3) -> Vec3 {
+        Vec3::new((p.x - self.view.x) / self.view.w, (p.y - self.view.y) / self.view.h, 1.0 - 0.001 * p.z)
+    }
+
+    // the part of the window r ends up on, in the same 0..1 as to_viewport
+    pub fn viewport_rect(&self, r: Rect) -> Rect {
+        Rect::new((r.x - self.view.x) / self.view.w, (r.y - self.view.y) / self.view.h, r.w / self.view.w, r.h / self.view.h)
+    }
+}
+
 pub struct Triangle {
     pub a: Vec2,
     pub b: Vec2,
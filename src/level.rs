@@ -1,3 +1,4 @@
+use crate::application::*;
 use crate::kgui::*;
 use crate::kmath::*;
 use crate::renderer::*;
@@ -11,24 +12,53 @@ use serde::{Serialize, Deserialize};
 
 pub type Tile = [u8;4];
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LevelMetadata {
+    // see migrate.rs, files are upgraded to LEVEL_FORMAT_VERSION on load
+    #[serde(default)]
+    pub version: u32,
     pub level: Level,
     pub name: String,
+    #[serde(default)]
+    pub author: String,
     pub rating: i32,
+    #[serde(default)]
+    pub par: Option<f32>,
+    // file this was loaded from, None for new levels and ones inside .pack files
+    #[serde(skip)]
+    pub source: Option<std::path::PathBuf>,
 }
 
 impl LevelMetadata {
-    pub fn save(&self, path: &str) {
+    pub fn new(level: Level) -> LevelMetadata {
+        LevelMetadata {
+            version: crate::migrate::LEVEL_FORMAT_VERSION,
+            level,
+            name: String::from("untitled"),
+            author: String::new(),
+            rating: 0,
+            par: None,
+            source: None,
+        }
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
         let str = serde_json::to_string(self).unwrap();
-        let mut f = File::create(path);
-        if f.is_ok() {
-            f.unwrap().write_all(str.as_bytes());
+        File::create(path)?.write_all(str.as_bytes())
+    }
+
+    pub fn save_text(&self, path: &str) -> std::io::Result<()> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
         }
+        File::create(path)?.write_all(crate::level_text::level_to_text(self).as_bytes())
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Level {
     pub tiles: Vec<Option<Tile>>,
     pub locked: Vec<bool>,
@@ -39,25 +69,111 @@ pub struct Level {
 }
 
 impl Level {
-    pub fn hash(&self) -> u32 {
-        let mut h = 0u32;
-        
-        for tile in self.tiles.iter() {
-            match tile {
-                Some(colours) => {
-                    for colour in colours {
-                        h += khash(*colour as u32);
-                        h = khash(h);
+    // Same for any rotation, reflection or recolouring of the puzzle, and covers the locks and palette too.
+    // fnv-1a over the canonical form then a murmur style finish to spread it out.
+    pub fn hash(&self) -> u64 {
+        let mut h = 0xcbf29ce484222325u64;
+        for b in self.canonical_form() {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x100000001b3);
+        }
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51afd7ed558ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+        h ^= h >> 33;
+        h
+    }
+
+    // The smallest serialization over the 8 board symmetries and every relabelling of the colours.
+    // Board colours are labelled in order of appearance, only colours that are just in the palette
+    // need trying in every order. Colours index straight into the labels, so the level has to have passed validate.
+    pub fn canonical_form(&self) -> Vec<u8> {
+        let mut best: Option<Vec<u8>> = None;
+        for base in [self.clone(), self.mirrored()] {
+            let mut level = base;
+            for _ in 0..4 {
+                let mut labels: Vec<Option<u8>> = vec![None; COLOURS.len()];
+                let mut next = 0;
+                for colour in level.tiles.iter().flatten().flatten() {
+                    if labels[*colour as usize].is_none() {
+                        labels[*colour as usize] = Some(next);
+                        next += 1;
                     }
-                },
-                None => {
-                    h += khash(666);
-                    h = khash(h);
-                },
+                }
+                let mut free: Vec<u8> = level.tile_palette.iter().flatten().map(|c| *c).filter(|c| labels[*c as usize].is_none()).collect();
+                free.sort();
+                free.dedup();
+
+                for order in permutations(&free) {
+                    let mut labels = labels.clone();
+                    for (i, colour) in order.iter().enumerate() {
+                        labels[*colour as usize] = Some(next + i as u8);
+                    }
+                    let form = level.serialize_labelled(&labels);
+                    if best.as_ref().map_or(true, |b| form < *b) {
+                        best = Some(form);
+                    }
+                }
+                level = level.rotated();
             }
         }
-        
-        h
+        best.unwrap()
+    }
+
+    // palette order and tile rotations in the palette dont matter to the puzzle so they're normalised away
+    fn serialize_labelled(&self, labels: &[Option<u8>]) -> Vec<u8> {
+        let relabel = |tile: &Tile| -> Tile { [0, 1, 2, 3].map(|i| labels[tile[i] as usize].unwrap()) };
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.w.to_le_bytes());
+        out.extend_from_slice(&self.h.to_le_bytes());
+        for (tile, locked) in self.tiles.iter().zip(self.locked.iter()) {
+            match tile {
+                Some(tile) => out.extend_from_slice(&relabel(tile)),
+                None => out.push(255),
+            }
+            out.push(*locked as u8);
+        }
+        out.push(254);
+        let mut palette: Vec<Tile> = self.tile_palette.iter().map(|t| canonical_rotation(relabel(t))).collect();
+        palette.sort();
+        palette.dedup();
+        for tile in palette.iter() {
+            out.extend_from_slice(tile);
+        }
+        out
+    }
+
+    // quarter turn clockwise, w and h swap
+    pub fn rotated(&self) -> Level {
+        let rotate = |t: Tile| [t[3], t[0], t[1], t[2]];
+        let mut out = Level::new(self.h, self.w);
+        for x in 0..self.w {
+            for y in 0..self.h {
+                if let Some(tile) = self.get_tile(x, y) {
+                    out.set_tile(self.h - 1 - y, x, rotate(tile));
+                }
+                out.set_locked(self.h - 1 - y, x, self.get_locked(x, y));
+            }
+        }
+        out.tile_palette = self.tile_palette.iter().map(|t| rotate(*t)).collect();
+        out
+    }
+
+    // left to right
+    pub fn mirrored(&self) -> Level {
+        let mirror = |t: Tile| [t[0], t[3], t[2], t[1]];
+        let mut out = Level::new(self.w, self.h);
+        for x in 0..self.w {
+            for y in 0..self.h {
+                if let Some(tile) = self.get_tile(x, y) {
+                    out.set_tile(self.w - 1 - x, y, mirror(tile));
+                }
+                out.set_locked(self.w - 1 - x, y, self.get_locked(x, y));
+            }
+        }
+        out.tile_palette = self.tile_palette.iter().map(|t| mirror(*t)).collect();
+        out
     }
 
     pub fn complexity(&self) -> u32 {
@@ -86,43 +202,240 @@ impl Level {
         }
     }
 
-    pub fn can_place(&self, x: i32, y: i32, place_tile: Tile) -> bool {
-        if x != 0 {
-            if let Some(neigh) = self.get_tile(x, y) {
-                if neigh[1] != place_tile[3] {
-                    println!("reject left edge neighbour");
-                    return false;
+    // Everything the rest of the game assumes about a level without checking. Whatever loads one from outside,
+    // json, text or a code, runs this so a bad file is an error instead of a crash later on.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.w < 1 || self.h < 1 {
+            return Err("size has to be at least 1 by 1");
+        }
+        if self.tiles.len() != (self.w * self.h) as usize {
+            return Err("number of tiles doesn't match the size");
+        }
+        if self.locked.len() != (self.w * self.h) as usize {
+            return Err("number of locks doesn't match the size");
+        }
+        if self.tile_palette.is_empty() {
+            return Err("empty palette");
+        }
+        let in_range = |tile: &Tile| tile.iter().all(|c| (*c as usize) < COLOURS.len());
+        if !self.tile_palette.iter().all(in_range) {
+            return Err("palette has a colour out of range");
+        }
+        if !self.tiles.iter().flatten().all(in_range) {
+            return Err("board has a colour out of range");
+        }
+        Ok(())
+    }
+
+    // Deterministic for a given seed: only integer khash, no floats, so its the same level on every machine.
+    // Builds a solved board from random edge colours, then keeps some of it locked as clues.
+    pub fn generate(w: i32, h: i32, num_colours: i32, seed: u32) -> Level {
+        let mut state = seed;
+        let mut next = |n: u32| {
+            state = khash(state.wrapping_add(1));
+            state % n
+        };
+
+        let mut colours: Vec<u8> = (0..COLOURS.len() as u8).collect();
+        for i in (1..colours.len()).rev() {
+            let j = next(i as u32 + 1) as usize;
+            colours.swap(i, j);
+        }
+        colours.truncate(num_colours.clamp(1, COLOURS.len() as i32) as usize);
+
+        // vertical edges are the left side of column x (x in 0..=w), horizontal edges the top of row y (y in 0..=h)
+        let vertical_edges: Vec<u8> = (0..(w+1)*h).map(|_| colours[next(colours.len() as u32) as usize]).collect();
+        let horizontal_edges: Vec<u8> = (0..w*(h+1)).map(|_| colours[next(colours.len() as u32) as usize]).collect();
+
+        let mut level = Level::new(w, h);
+        level.tile_palette.clear();
+        let mut solution = Vec::new();
+
+        for i in 0..w {
+            for j in 0..h {
+                let tile = [
+                    horizontal_edges[(i*(h+1) + j) as usize],
+                    vertical_edges[((i+1)*h + j) as usize],
+                    horizontal_edges[(i*(h+1) + j + 1) as usize],
+                    vertical_edges[(i*h + j) as usize],
+                ];
+                let palette_tile = canonical_rotation(tile);
+                if !level.tile_palette.contains(&palette_tile) {
+                    level.tile_palette.push(palette_tile);
                 }
+                solution.push((i, j, tile));
             }
         }
-    
-        if x != self.w - 1 {
-            if let Some(neigh) = self.get_tile(x + 1, y) {
-                if neigh[3] != place_tile[1] {
-                    println!("reject right edge neighbour");
-                    return false;
+
+        let mut clues: Vec<(i32, i32, Tile)> = solution.iter().filter(|_| next(4) == 0).map(|x| *x).collect();
+        if clues.is_empty() {
+            clues.push(solution[next(solution.len() as u32) as usize]);
+        }
+        for (i, j, tile) in clues {
+            level.set_tile(i, j, tile);
+            level.set_locked(i, j, true);
+        }
+
+        level.tile_palette.sort();
+        level
+    }
+
+    pub fn is_solved(&self) -> bool {
+        for i in 0..self.w {
+            for j in 0..self.h {
+                let tile = match self.get_tile(i, j) {
+                    Some(tile) => tile,
+                    None => return false,
+                };
+                if i != self.w - 1 {
+                    if let Some(neigh) = self.get_tile(i + 1, j) {
+                        if neigh[3] != tile[1] {
+                            return false;
+                        }
+                    }
+                }
+                if j != self.h - 1 {
+                    if let Some(neigh) = self.get_tile(i, j + 1) {
+                        if neigh[0] != tile[2] {
+                            return false;
+                        }
+                    }
                 }
             }
         }
-    
-        if y != self.h - 1 {
-            if let Some(neigh) = self.get_tile(x, y + 1) {
-                if neigh[0] != place_tile[2] {
-                    println!("reject bottom edge neighbour");
+        true
+    }
+
+    // Whether tile could go at x, y, giving up at the first side that doesnt match. The solver calls this for
+    // every candidate so it doesnt build a Placement, can_place is for when the UI wants to know which sides.
+    pub fn fits(&self, x: i32, y: i32, tile: Tile) -> bool {
+        for side in 0..4 {
+            let (nx, ny) = neighbour(x, y, side);
+            if nx < 0 || ny < 0 || nx >= self.w || ny >= self.h {
+                continue;
+            }
+            if let Some(neigh) = self.get_tile(nx, ny) {
+                if neigh[(side + 2) % 4] != tile[side] {
                     return false;
                 }
             }
         }
-        
-        if y != 0 {
-            if let Some(neigh) = self.get_tile(x, y - 1) {
-                if neigh[2] != place_tile[0] {
-                    println!("reject top edge neighbour");
-                    return false;
+        true
+    }
+
+    // Every side of tile that wouldnt match the neighbour across it
+    pub fn can_place(&self, x: i32, y: i32, tile: Tile) -> Placement {
+        let mut placement = Placement::default();
+        for side in 0..4 {
+            let (nx, ny) = neighbour(x, y, side);
+            if nx < 0 || ny < 0 || nx >= self.w || ny >= self.h {
+                continue;
+            }
+            if let Some(neigh) = self.get_tile(nx, ny) {
+                let theirs = neigh[(side + 2) % 4];
+                if theirs != tile[side] {
+                    placement.conflicts[side] = Some(EdgeConflict { side, neighbour: (nx, ny), ours: tile[side], theirs });
                 }
             }
         }
-        true
+        placement
+    }
+
+    // Backtracking search keeping whatever tiles are already down. Palette tiles can be used any number of times in any rotation.
+    // Gives up once its tried max_tried tiles so nothing can hang on a huge board, Err is how many it got through
+    pub fn solve_limited(&self, max_tried: u64) -> Result<Option<Level>, u64> {
+        let candidates = self.candidates();
+        let mut level = self.clone();
+        let mut tried = 0;
+        if level.solve_from(0, &candidates, &mut tried, max_tried) {
+            Ok(Some(level))
+        } else if tried >= max_tried {
+            Err(tried)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Same search but it keeps going, stops counting at limit or after max_tried tiles. Also returns how many tiles it tried, which is a rough difficulty
+    pub fn count_solutions(&self, limit: usize, max_tried: u64) -> (usize, u64) {
+        let candidates = self.candidates();
+        let mut level = self.clone();
+        let mut solutions = 0;
+        let mut tried = 0;
+        level.count_from(0, &candidates, limit, max_tried, &mut solutions, &mut tried);
+        (solutions, tried)
+    }
+
+    fn candidates(&self) -> Vec<Tile> {
+        let mut candidates = Vec::new();
+        for tile in self.tile_palette.iter() {
+            let mut rotated = *tile;
+            for _ in 0..4 {
+                if !candidates.contains(&rotated) {
+                    candidates.push(rotated);
+                }
+                rotated = [rotated[1], rotated[2], rotated[3], rotated[0]];
+            }
+        }
+        candidates
+    }
+
+    fn count_from(&mut self, idx: i32, candidates: &[Tile], limit: usize, max_tried: u64, solutions: &mut usize, tried: &mut u64) {
+        if *solutions >= limit || *tried >= max_tried {
+            return;
+        }
+        if idx == self.w * self.h {
+            *solutions += 1;
+            return;
+        }
+        let x = idx / self.h;
+        let y = idx % self.h;
+
+        if let Some(tile) = self.get_tile(x, y) {
+            if self.fits(x, y, tile) {
+                self.count_from(idx + 1, candidates, limit, max_tried, solutions, tried);
+            }
+            return;
+        }
+
+        for &tile in candidates {
+            if *tried >= max_tried {
+                return;
+            }
+            *tried += 1;
+            if self.fits(x, y, tile) {
+                self.set_tile(x, y, tile);
+                self.count_from(idx + 1, candidates, limit, max_tried, solutions, tried);
+                self.clear_tile(x, y);
+            }
+        }
+    }
+
+    fn solve_from(&mut self, idx: i32, candidates: &[Tile], tried: &mut u64, max_tried: u64) -> bool {
+        if idx == self.w * self.h {
+            return true;
+        }
+        let x = idx / self.h;
+        let y = idx % self.h;
+
+        if let Some(tile) = self.get_tile(x, y) {
+            return self.fits(x, y, tile) && self.solve_from(idx + 1, candidates, tried, max_tried);
+        }
+
+        for &tile in candidates {
+            if *tried >= max_tried {
+                return false;
+            }
+            *tried += 1;
+            if self.fits(x, y, tile) {
+                self.set_tile(x, y, tile);
+                if self.solve_from(idx + 1, candidates, tried, max_tried) {
+                    return true;
+                }
+                self.clear_tile(x, y);
+            }
+        }
+        false
     }
 
     pub fn set_tile(&mut self, x: i32, y: i32, tile: Tile) {
@@ -186,24 +499,129 @@ impl Level {
         self.locked = new_locked;
     }
 
-    pub fn frame(&self, buf: &mut TriangleBuffer, buf_uv: &mut TriangleBufferUV,  rect: Rect, inputs: &FrameInputState, selected_tile: Option<i32>) -> (Option<i32>, Option<(i32, i32)>) {
+    // Where frame puts everything when its drawn into rect, so scenes can hit test points other than the mouse.
+    // The board is drawn into level_pane, zoomed in it goes past the edges and gets cut off
+    fn level_pane(&self, rect: Rect) -> Rect {
+        rect.child(0.2, 0.0, 0.8, 1.0).fit_aspect_ratio(self.w as f32 / self.h as f32)
+    }
+
+    // the whole board, most of it off the pane when zoomed in
+    fn board_rect(&self, rect: Rect, camera: &BoardCamera) -> Rect {
+        let pane = self.level_pane(rect);
+        let (w, h) = (pane.w * camera.zoom, pane.h * camera.zoom);
+        Rect::new(pane.x + pane.w / 2.0 - camera.centre.x * w, pane.y + pane.h / 2.0 - camera.centre.y * h, w, h)
+    }
+
+    pub fn palette_tile_rect(&self, rect: Rect, i: i32) -> Rect {
         let tiles_pane = rect.child(0.0, 0.0, 0.2, 1.0);
-        let level_pane = rect.child(0.2, 0.0, 0.8, 1.0).fit_aspect_ratio(self.w as f32 / self.h as f32);
-        let level_rect = level_pane.dilate(-0.005);
+        tiles_pane.grid_child(0, i, 1, self.tile_palette.len() as i32).dilate(-0.01).fit_center_square()
+    }
 
-        buf.draw_rect(level_pane, Vec3::new(0.2, 0.2, 0.2), 1.0);
+    pub fn grid_tile_rect(&self, rect: Rect, camera: &BoardCamera, x: i32, y: i32) -> Rect {
+        let gap = 0.005 * camera.zoom;
+        self.board_rect(rect, camera).dilate(-gap).grid_child(x, y, self.w, self.h).dilate(-gap)
+    }
 
-        // buf.draw_rect(tiles_pane, Vec3::new(0.0, 1.0, 0.0), 100.0);
-        // buf.draw_rect(level_pane, Vec3::new(1.0, 1.0, 0.0), 100.0);
+    // where a cell goes in the board layer, which is laid out unzoomed and left to its camera
+    pub fn board_cell_rect(&self, rect: Rect, x: i32, y: i32) -> Rect {
+        self.grid_tile_rect(rect, &BoardCamera::default(), x, y)
+    }
 
-        let mut select_palette_tile = None;
-        let mut select_grid_tile = None;
+    // in the corner of the pane once its zoomed in enough that you could get lost
+    pub fn minimap_rect(&self, rect: Rect, camera: &BoardCamera) -> Option<Rect> {
+        if camera.zoom < MINIMAP_ZOOM {
+            return None;
+        }
+        let pane = self.level_pane(rect);
+        let size = pane.w.min(pane.h) * 0.3;
+        Some(Rect::new(pane.right() - size - 0.01, pane.y + 0.01, size, size).fit_aspect_ratio(self.w as f32 / self.h as f32))
+    }
 
-        for (i, tile) in self.tile_palette.iter().enumerate() {
-            let tile_rect = tiles_pane.grid_child(0, i as i32, 1, self.tile_palette.len() as i32).dilate(-0.01).fit_center_square();
-            if tile_rect.contains(inputs.mouse_pos) {
-                select_palette_tile = Some(i as i32);
+    pub fn palette_at(&self, rect: Rect, p: Vec2) -> Option<i32> {
+        (0..self.tile_palette.len() as i32).find(|i| self.palette_tile_rect(rect, *i).contains(p))
+    }
+
+    // cells cut off by the edge of the pane or under the minimap cant be pointed at
+    pub fn grid_at(&self, rect: Rect, camera: &BoardCamera, p: Vec2) -> Option<(i32, i32)> {
+        if !self.level_pane(rect).contains(p) || self.minimap_rect(rect, camera).map_or(false, |m| m.contains(p)) {
+            return None;
+        }
+        let board = self.board_rect(rect, camera);
+        let x = ((p.x - board.x) / board.w * self.w as f32).floor() as i32;
+        let y = ((p.y - board.y) / board.h * self.h as f32).floor() as i32;
+        if x < 0 || y < 0 || x >= self.w || y >= self.h || !self.grid_tile_rect(rect, camera, x, y).contains(p) {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    // What placing tile at x, y would look like, see through over whats there. Goes in the board layer after the
+    // board so the board shows through it.
+    pub fn draw_ghost(&self, buf: &mut TriangleBuffer, rect: Rect, x: i32, y: i32, tile: Tile) {
+        self.draw_ghost_placement(buf, rect, x, y, tile, None);
+    }
+
+    // Same but red with a bar on each side that doesnt match when it cant go there, for playing. The editor puts anything anywhere.
+    pub fn draw_ghost_checked(&self, buf: &mut TriangleBuffer, rect: Rect, x: i32, y: i32, tile: Tile) {
+        let placement = self.can_place(x, y, tile);
+        self.draw_ghost_placement(buf, rect, x, y, tile, Some(placement));
+    }
+
+    fn draw_ghost_placement(&self, buf: &mut TriangleBuffer, rect: Rect, x: i32, y: i32, tile: Tile, placement: Option<Placement>) {
+        let cell = self.board_cell_rect(rect, x, y);
+        let rejected = placement.as_ref().map_or(false, |p| !p.fits());
+        for (side, colour) in tile.iter().enumerate() {
+            let colour = if rejected { COLOURS[*colour as usize].lerp(REJECT_COLOUR, 0.6) } else { COLOURS[*colour as usize] };
+            buf.draw_tri_alpha(cell.tri_child(side), colour.with_alpha(GHOST_ALPHA), GHOST_DEPTH);
+        }
+        for conflict in placement.iter().flat_map(|p| p.conflicts()) {
+            buf.draw_rect(edge_rect(cell, conflict.side), REJECT_COLOUR, GHOST_DEPTH + 1.0);
+        }
+    }
+
+    // all of it unzoomed into the same buffers, for previews
+    pub fn frame(&self, buf: &mut TriangleBuffer, buf_uv: &mut TriangleBufferUV,  rect: Rect, inputs: &FrameInputState, selected_tile: Option<i32>) -> (Option<i32>, Option<(i32, i32)>) {
+        buf.draw_rect(self.level_pane(rect), Vec3::new(0.2, 0.2, 0.2), 1.0);
+        self.draw_palette(buf, rect, selected_tile);
+        self.draw_board(buf, buf_uv, rect);
+        (self.palette_at(rect, inputs.mouse_pos), self.grid_at(rect, &BoardCamera::default(), inputs.mouse_pos))
+    }
+
+    // The board goes in the board layer from camera.layer and the rest onto the screen, the hit testing goes by the camera
+    pub fn frame_camera(&self, buf: &mut TriangleBuffer, board: &mut Layer, rect: Rect, inputs: &FrameInputState, selected_tile: Option<i32>, camera: &BoardCamera) -> (Option<i32>, Option<(i32, i32)>) {
+        buf.draw_rect(self.level_pane(rect), Vec3::new(0.2, 0.2, 0.2), 1.0);
+        self.draw_palette(buf, rect, selected_tile);
+        let board_uv = board.tri_uvs.get_or_insert_with(|| TriangleBufferUV::new(ATLAS_W, ATLAS_H));
+        self.draw_board(&mut board.tris, board_uv, rect);
+
+
+        // the whole board small with a box around whats on screen
+        if let Some(minimap) = self.minimap_rect(rect, camera) {
+            buf.draw_rect(minimap.dilate(0.005), Vec3::new(0.05, 0.05, 0.05), 6.0);
+            for i in 0..self.w {
+                for j in 0..self.h {
+                    let cell = minimap.grid_child(i, j, self.w, self.h);
+                    match self.get_tile(i, j) {
+                        Some(colours) => draw_tile_depth(buf, cell, colours, 7.0),
+                        None => buf.draw_rect(cell, Vec3::new(0.15, 0.15, 0.15), 7.0),
+                    }
+                }
             }
+            let view = Rect::new(
+                minimap.x + (camera.centre.x - 0.5 / camera.zoom) * minimap.w,
+                minimap.y + (camera.centre.y - 0.5 / camera.zoom) * minimap.h,
+                minimap.w / camera.zoom,
+                minimap.h / camera.zoom,
+            );
+            buf.draw_outline(view, 0.003, Vec3::new(1.0, 1.0, 1.0), 8.0);
+        }
+
+        (self.palette_at(rect, inputs.mouse_pos), self.grid_at(rect, camera, inputs.mouse_pos))
+    }
+
+    fn draw_palette(&self, buf: &mut TriangleBuffer, rect: Rect, selected_tile: Option<i32>) {
+        for (i, tile) in self.tile_palette.iter().enumerate() {
+            let tile_rect = self.palette_tile_rect(rect, i as i32);
             draw_tile(buf, tile_rect, *tile);
             if let Some(idx) = selected_tile {
                 if idx as usize == i {
@@ -211,13 +629,12 @@ impl Level {
                 }
             }
         }
+    }
 
+    fn draw_board(&self, buf: &mut TriangleBuffer, buf_uv: &mut TriangleBufferUV, rect: Rect) {
         for i in 0..self.w {
             for j in 0..self.h {
-                let tile_rect = level_rect.grid_child(i, j, self.w, self.h).dilate(-0.005);
-                if tile_rect.contains(inputs.mouse_pos) {
-                    select_grid_tile = Some((i, j));
-                }
+                let tile_rect = self.board_cell_rect(rect, i, j);
                 if let Some(colours) = self.get_tile(i, j) {
                     draw_tile(buf, tile_rect, colours);
                     if !self.get_locked(i, j) {
@@ -228,13 +645,244 @@ impl Level {
                 }
             }
         }
+    }
+}
+
+
+const ZOOM_STEP: f32 = 1.25;
+// zoomed all the way in there are still this many cells across
+const MIN_CELLS_SHOWN: f32 = 4.0;
+const MINIMAP_ZOOM: f32 = 2.0;
+
+// How much of the board frame_camera shows. Zoom 1 is all of it, centre is the middle of the view as a
+// fraction of the board so it stays about right if the board changes size. The board is always drawn
+// unzoomed, this only picks the view the board layer is presented through
+#[derive(Clone, Copy, Debug)]
+pub struct BoardCamera {
+    pub zoom: f32,
+    pub centre: Vec2,
+}
+
+impl Default for BoardCamera {
+    fn default() -> BoardCamera {
+        BoardCamera { zoom: 1.0, centre: Vec2::new(0.5, 0.5) }
+    }
+}
+
+impl BoardCamera {
+    // An empty board layer whose camera puts the unzoomed board where this camera has it, cut off at the
+    // edge of the pane. offset moves the whole board, for shaking it
+    pub fn layer(&self, level: &Level, rect: Rect, screen_rect: Rect, offset: Vec2) -> Layer {
+        let home = level.board_rect(rect, &BoardCamera::default());
+        let shift = level.board_rect(rect, self).tl() - home.tl() * self.zoom + offset;
+        let view = Rect::new((screen_rect.x - shift.x) / self.zoom, (screen_rect.y - shift.y) / self.zoom, screen_rect.w / self.zoom, screen_rect.h / self.zoom);
+        Layer {
+            camera: Camera::new(view),
+            clip: Some(Camera::new(screen_rect).viewport_rect(level.level_pane(rect))),
+            tris: TriangleBuffer::new(),
+            tri_uvs: Some(TriangleBufferUV::new(ATLAS_W, ATLAS_H)),
+        }
+    }
+
+    // the view never goes past the edge of the board
+    fn clamp(&mut self, level: &Level) {
+        let max_zoom = (level.w.max(level.h) as f32 / MIN_CELLS_SHOWN).max(1.0);
+        self.zoom = self.zoom.max(1.0).min(max_zoom);
+        let half = 0.5 / self.zoom;
+        self.centre.x = self.centre.x.max(half).min(1.0 - half);
+        self.centre.y = self.centre.y.max(half).min(1.0 - half);
+    }
+
+    // keeps whatever is under p where it is
+    pub fn zoom_at(&mut self, level: &Level, rect: Rect, p: Vec2, steps: i32) {
+        let before = level.board_rect(rect, self);
+        let on_board = Vec2::new((p.x - before.x) / before.w, (p.y - before.y) / before.h);
+        let pane = level.level_pane(rect);
+        self.zoom *= ZOOM_STEP.powi(steps);
+        self.clamp(level);
+        let from_middle = p - pane.centroid();
+        self.centre = Vec2::new(on_board.x - from_middle.x / (pane.w * self.zoom), on_board.y - from_middle.y / (pane.h * self.zoom));
+        self.clamp(level);
+    }
+
+    // the board moves with the mouse
+    pub fn pan(&mut self, level: &Level, rect: Rect, delta: Vec2) {
+        let board = level.board_rect(rect, self);
+        self.centre = Vec2::new(self.centre.x - delta.x / board.w, self.centre.y - delta.y / board.h);
+        self.clamp(level);
+    }
+
+    // moves just far enough that the cell is in view, for the keyboard cursor
+    pub fn show_cell(&mut self, level: &Level, x: i32, y: i32) {
+        let half = 0.5 / self.zoom;
+        let (cell_w, cell_h) = (1.0 / level.w as f32, 1.0 / level.h as f32);
+        let (left, top) = (x as f32 * cell_w, y as f32 * cell_h);
+        self.centre.x = self.centre.x.max(left + cell_w - half).min(left + half);
+        self.centre.y = self.centre.y.max(top + cell_h - half).min(top + half);
+        self.clamp(level);
+    }
+
+    // Wheel zooms, dragging with panning held moves the board and so does holding the left button on the minimap.
+    // True if the mouse was busy with the camera this frame and shouldn't do anything else.
+    pub fn update(&mut self, level: &Level, rect: Rect, inputs: &FrameInputState, panning: bool) -> bool {
+        // the editor can resize the board out from under it
+        self.clamp(level);
+        let pane = level.level_pane(rect);
+        let steps = inputs.wheel_steps(pane);
+        if steps != 0 {
+            self.zoom_at(level, rect, inputs.mouse_pos, -steps);
+        }
+        if let Some(minimap) = level.minimap_rect(rect, self) {
+            if inputs.held_lmb && inputs.lmb_down_in(minimap) {
+                self.centre = Vec2::new((inputs.mouse_pos.x - minimap.x) / minimap.w, (inputs.mouse_pos.y - minimap.y) / minimap.h);
+                self.clamp(level);
+                return true;
+            }
+        }
+        if panning {
+            for e in inputs.events.iter() {
+                if let KEvent::MouseMotion(delta) = e {
+                    self.pan(level, rect, *delta);
+                }
+            }
+        }
+        panning
+    }
+}
+
+// every ordering of items, for the colours canonical_form has to try in turn
+fn permutations(items: &[u8]) -> Vec<Vec<u8>> {
+    if items.is_empty() {
+        return vec![Vec::new()];
+    }
+    let mut out = Vec::new();
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(i);
+        for mut perm in permutations(&rest) {
+            perm.insert(0, first);
+            out.push(perm);
+        }
+    }
+    out
+}
+
+// the player can rotate freely so palette tiles only need to be unique up to rotation
+pub fn canonical_rotation(tile: Tile) -> Tile {
+    let mut best = tile;
+    let mut rotated = tile;
+    for _ in 0..3 {
+        rotated = [rotated[1], rotated[2], rotated[3], rotated[0]];
+        if rotated < best {
+            best = rotated;
+        }
+    }
+    best
+}
+
+const GHOST_ALPHA: f32 = 0.5;
+const GHOST_DEPTH: f32 = 6.0;
+const REJECT_COLOUR: Vec3 = Vec3::new(1.0, 0.1, 0.1);
+
+// the cell across side, in the same order as a tiles colours
+pub fn neighbour(x: i32, y: i32, side: usize) -> (i32, i32) {
+    match side {
+        0 => (x, y - 1),
+        1 => (x + 1, y),
+        2 => (x, y + 1),
+        _ => (x - 1, y),
+    }
+}
+
+// a bar along the inside of one side of cell
+pub fn edge_rect(cell: Rect, side: usize) -> Rect {
+    let t = cell.w.min(cell.h) * 0.1;
+    match side {
+        0 => Rect::new(cell.x, cell.y, cell.w, t),
+        1 => Rect::new(cell.right() - t, cell.y, t, cell.h),
+        2 => Rect::new(cell.x, cell.bot() - t, cell.w, t),
+        _ => Rect::new(cell.x, cell.y, t, cell.h),
+    }
+}
+
+// One side of a tile that doesnt match the tile next to it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EdgeConflict {
+    // 0 top, 1 right, 2 bottom, 3 left, like the tiles colours
+    pub side: usize,
+    pub neighbour: (i32, i32),
+    pub ours: u8,
+    pub theirs: u8,
+}
+
+// What can_place found, a conflict for each side that doesnt match
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Placement {
+    pub conflicts: [Option<EdgeConflict>; 4],
+}
+
+impl Placement {
+    pub fn fits(&self) -> bool {
+        self.conflicts.iter().all(|c| c.is_none())
+    }
 
-        (select_palette_tile, select_grid_tile)
+    pub fn conflicts(&self) -> impl Iterator<Item = &EdgeConflict> {
+        self.conflicts.iter().flatten()
     }
 }
 
 pub fn draw_tile(buf: &mut TriangleBuffer, rect: Rect, tile: Tile) {
+    draw_tile_depth(buf, rect, tile, 3.0);
+}
+
+// for tiles that go over the board, like one being dragged
+pub fn draw_tile_depth(buf: &mut TriangleBuffer, rect: Rect, tile: Tile, depth: f32) {
     for (x, colour) in tile.iter().enumerate() {
-        buf.draw_tri(rect.tri_child(x), COLOURS[*colour as usize], 3.0);
+        buf.draw_tri(rect.tri_child(x), COLOURS[*colour as usize], depth);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every colour moved along by shift
+    fn recoloured(level: &Level, shift: u8) -> Level {
+        let recolour = |t: Tile| t.map(|c| (c + shift) % COLOURS.len() as u8);
+        let mut out = level.clone();
+        out.tiles = level.tiles.iter().map(|t| t.map(recolour)).collect();
+        out.tile_palette = level.tile_palette.iter().map(|t| recolour(*t)).collect();
+        out
+    }
+
+    #[test]
+    fn hash_ignores_symmetry_and_colours() {
+        let level = Level::generate(4, 3, 3, 7);
+        let hash = level.hash();
+        assert_eq!(level.rotated().hash(), hash);
+        assert_eq!(level.rotated().rotated().hash(), hash);
+        assert_eq!(level.mirrored().hash(), hash);
+        assert_eq!(level.mirrored().rotated().hash(), hash);
+        assert_eq!(recoloured(&level, 1).hash(), hash);
+        assert_eq!(recoloured(&level.mirrored(), 3).hash(), hash);
+    }
+
+    #[test]
+    fn hash_sees_locks_and_palette() {
+        let level = Level::generate(4, 3, 3, 7);
+        let hash = level.hash();
+
+        let mut unlocked = level.clone();
+        unlocked.locked[0] = !unlocked.locked[0];
+        assert_ne!(unlocked.hash(), hash);
+
+        // a tile thats not already in the palette in any rotation
+        let have: Vec<Tile> = level.tile_palette.iter().map(|t| canonical_rotation(*t)).collect();
+        let mut colours: Vec<u8> = level.tile_palette.iter().flatten().copied().collect();
+        colours.sort();
+        colours.dedup();
+        let new_tile = (0..16u8).map(|n| [0, 1, 2, 3].map(|i| colours[(n >> i & 1) as usize])).find(|t| !have.contains(&canonical_rotation(*t))).unwrap();
+        let mut more = level.clone();
+        more.tile_palette.push(new_tile);
+        assert_ne!(more.hash(), hash);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::level::*;
+use crate::kmath::*;
+
+use std::fmt;
+
+// Short ASCII codes for passing levels around in chat.
+//
+// Layout, most significant bit first:
+//   4   code version
+//   8   w - 1, 8 h - 1
+//   12  palette length, then 12 bits per tile (3 bits per colour)
+//   12  number of distinct tiles on the board, then 12 bits each
+//   runs of identical cells until the board is full, column major like Level::tiles:
+//       length - 1 as 3 bit groups each followed by a continue bit
+//       locked bit, then 0 for empty or 1 and an index into the board tiles
+// then 16 bits of checksum over all of the above and the lot as base64url without padding.
+
+pub const LEVEL_CODE_PATH: &str = "level.code";
+
+const CODE_VERSION: u32 = 1;
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[derive(Debug)]
+pub enum LevelCodeError {
+    BadCharacter(char),
+    Truncated,
+    BadChecksum,
+    UnknownVersion(u32),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for LevelCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelCodeError::BadCharacter(c) => write!(f, "'{}' can't be in a level code", c),
+            LevelCodeError::Truncated => write!(f, "level code is cut short"),
+            LevelCodeError::BadChecksum => write!(f, "level code checksum doesn't match, probably mistyped"),
+            LevelCodeError::UnknownVersion(v) => write!(f, "level code version {} is unknown", v),
+            LevelCodeError::Invalid(why) => write!(f, "level code is invalid: {}", why),
+        }
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit: usize,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter { bytes: Vec::new(), bit: 0 }
+    }
+
+    fn write(&mut self, value: u32, bits: usize) {
+        for i in (0..bits).rev() {
+            if self.bit % 8 == 0 {
+                self.bytes.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                let last = self.bytes.len() - 1;
+                self.bytes[last] |= 0x80 >> (self.bit % 8);
+            }
+            self.bit += 1;
+        }
+    }
+
+    fn write_run(&mut self, mut value: u32) {
+        loop {
+            self.write(value & 0b111, 3);
+            value >>= 3;
+            self.write((value != 0) as u32, 1);
+            if value == 0 {
+                break;
+            }
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, bits: usize) -> Result<u32, LevelCodeError> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = *self.bytes.get(self.bit / 8).ok_or(LevelCodeError::Truncated)?;
+            value = (value << 1) | ((byte >> (7 - self.bit % 8)) & 1) as u32;
+            self.bit += 1;
+        }
+        Ok(value)
+    }
+
+    fn read_run(&mut self) -> Result<u32, LevelCodeError> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            value |= self.read(3)? << shift;
+            shift += 3;
+            if self.read(1)? == 0 || shift > 24 {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+fn bits_for(n: usize) -> usize {
+    let mut bits = 0;
+    while (1 << bits) < n {
+        bits += 1;
+    }
+    bits
+}
+
+fn pack_tile(tile: Tile) -> u32 {
+    tile.iter().fold(0, |acc, c| (acc << 3) | *c as u32)
+}
+
+fn unpack_tile(packed: u32) -> Result<Tile, LevelCodeError> {
+    let tile = [(packed >> 9) as u8 & 7, (packed >> 6) as u8 & 7, (packed >> 3) as u8 & 7, packed as u8 & 7];
+    if tile.iter().any(|c| *c as usize >= crate::manifest::COLOURS.len()) {
+        return Err(LevelCodeError::Invalid("colour out of range"));
+    }
+    Ok(tile)
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |h, b| khash(h.wrapping_add(*b as u32))) & 0xFFFF
+}
+
+fn base64url_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[((n >> (18 - 6 * i)) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+fn base64url_decode(code: &str) -> Result<Vec<u8>, LevelCodeError> {
+    let mut sextets = Vec::new();
+    for c in code.chars().filter(|c| !c.is_whitespace()) {
+        match ALPHABET.iter().position(|a| *a as char == c) {
+            Some(idx) => sextets.push(idx as u32),
+            None => return Err(LevelCodeError::BadCharacter(c)),
+        }
+    }
+    let mut out = Vec::new();
+    for chunk in sextets.chunks(4) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, s)| acc | s << (18 - 6 * i));
+        for i in 0..chunk.len().saturating_sub(1) {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+pub fn encode_level(level: &Level) -> Result<String, LevelCodeError> {
+    if level.w > 256 || level.h > 256 {
+        return Err(LevelCodeError::Invalid("board too big for a code"));
+    }
+    let mut board_tiles: Vec<Tile> = Vec::new();
+    for tile in level.tiles.iter().flatten() {
+        if !board_tiles.contains(tile) {
+            board_tiles.push(*tile);
+        }
+    }
+    let idx_bits = bits_for(board_tiles.len());
+
+    let mut w = BitWriter::new();
+    w.write(CODE_VERSION, 4);
+    w.write((level.w - 1) as u32, 8);
+    w.write((level.h - 1) as u32, 8);
+    w.write(level.tile_palette.len() as u32, 12);
+    for tile in level.tile_palette.iter() {
+        w.write(pack_tile(*tile), 12);
+    }
+    w.write(board_tiles.len() as u32, 12);
+    for tile in board_tiles.iter() {
+        w.write(pack_tile(*tile), 12);
+    }
+
+    let cells: Vec<(Option<Tile>, bool)> = level.tiles.iter().zip(level.locked.iter()).map(|(t, l)| (*t, *l)).collect();
+    let mut i = 0;
+    while i < cells.len() {
+        let mut run = 1;
+        while i + run < cells.len() && cells[i + run] == cells[i] {
+            run += 1;
+        }
+        w.write_run(run as u32 - 1);
+        let (tile, locked) = cells[i];
+        w.write(locked as u32, 1);
+        match tile {
+            None => w.write(0, 1),
+            Some(tile) => {
+                w.write(1, 1);
+                w.write(board_tiles.iter().position(|t| *t == tile).unwrap() as u32, idx_bits);
+            },
+        }
+        i += run;
+    }
+
+    let mut bytes = w.bytes;
+    let sum = checksum(&bytes);
+    bytes.push((sum >> 8) as u8);
+    bytes.push(sum as u8);
+    let code = base64url_encode(&bytes);
+
+    // cheap enough to always do, a code that comes back different is worse than no code
+    match decode_level(&code) {
+        Ok(decoded) if decoded == *level => Ok(code),
+        _ => Err(LevelCodeError::Invalid("code doesn't decode back to the same level")),
+    }
+}
+
+pub fn decode_level(code: &str) -> Result<Level, LevelCodeError> {
+    let bytes = base64url_decode(code)?;
+    if bytes.len() < 3 {
+        return Err(LevelCodeError::Truncated);
+    }
+    let (payload, sum) = bytes.split_at(bytes.len() - 2);
+    if checksum(payload) != ((sum[0] as u32) << 8 | sum[1] as u32) {
+        return Err(LevelCodeError::BadChecksum);
+    }
+
+    let mut r = BitReader { bytes: payload, bit: 0 };
+    let version = r.read(4)?;
+    if version != CODE_VERSION {
+        return Err(LevelCodeError::UnknownVersion(version));
+    }
+    let w = r.read(8)? as i32 + 1;
+    let h = r.read(8)? as i32 + 1;
+    let mut level = Level::new(w, h);
+
+    let palette_len = r.read(12)?;
+    if palette_len == 0 {
+        return Err(LevelCodeError::Invalid("empty palette"));
+    }
+    level.tile_palette = (0..palette_len).map(|_| unpack_tile(r.read(12)?)).collect::<Result<_, _>>()?;
+
+    let board_len = r.read(12)? as usize;
+    let board_tiles: Vec<Tile> = (0..board_len).map(|_| unpack_tile(r.read(12)?)).collect::<Result<_, _>>()?;
+    let idx_bits = bits_for(board_len);
+
+    let mut i = 0;
+    while i < (w * h) as usize {
+        let run = r.read_run()? as usize + 1;
+        let locked = r.read(1)? == 1;
+        let cell = if r.read(1)? == 0 {
+            (None, locked)
+        } else {
+            (Some(*board_tiles.get(r.read(idx_bits)? as usize).ok_or(LevelCodeError::Invalid("tile index out of range"))?), locked)
+        };
+        if i + run > (w * h) as usize {
+            return Err(LevelCodeError::Invalid("run goes off the board"));
+        }
+        for j in i..i + run {
+            level.tiles[j] = cell.0;
+            level.locked[j] = cell.1;
+        }
+        i += run;
+    }
+
+    level.validate().map_err(LevelCodeError::Invalid)?;
+    Ok(level)
+}
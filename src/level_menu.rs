@@ -1,4 +1,3 @@
-use crate::level::*;
 use crate::application::*;
 use crate::renderer::*;
 use crate::rendererUV::*;
@@ -6,105 +5,217 @@ use crate::manifest::*;
 use crate::kmath::*;
 use crate::kgui::*;
 use crate::rendererUV::TriangleBufferUV;
+use crate::game::*;
+use crate::progress::*;
+use crate::pack::*;
+use crate::bindings::*;
+
+use serde::{Serialize, Deserialize};
 
 use std::collections::HashMap;
-use std::fs::*;
-use std::io::Read;
 
 use glutin::event::ElementState;
-use glutin::event::VirtualKeyCode;
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MenuCommand {
     Move((i32, i32)),
+    Page(i32),
+    Highlight(i32),
     Select,
+    Play,
     Quit,
+    Help,
 }
 
 pub struct LevelMenu {
     selection: i32,
-    levels: Vec<LevelMetadata>,
+    pack: LevelPack,
+    // true for levels that are the same puzzle as another one in the pack
+    duplicate: Vec<bool>,
     width: i32,
+    height: i32,
+
+    progress: SharedProgress,
+    bindings: SharedBindings,
 }
 
 impl LevelMenu {
-    pub fn new() -> LevelMenu {
-        let mut levels: Vec<LevelMetadata> = Vec::new();
-        let entries = read_dir("levels/").unwrap();
-        for entry in entries {
-            let path = entry.unwrap().path();
-            if !path.is_dir() {
-                let mut f = File::open(path).unwrap();
-                let mut contents = String::new();
-                f.read_to_string(&mut contents).unwrap();
-
-                let level_metadata = serde_json::from_str(&contents);
-                levels.push(level_metadata.unwrap());
+    pub fn new(pack: LevelPack, progress: SharedProgress, bindings: SharedBindings) -> LevelMenu {
+        println!("pack {}: {} levels", pack.meta.name, pack.levels.len());
+        let mut duplicate = vec![false; pack.levels.len()];
+        for group in pack.duplicates() {
+            let names: Vec<String> = group.iter().map(|i| format!("{} ({})", pack.levels[*i].name, i + 1)).collect();
+            println!("duplicate puzzles: {}", names.join(", "));
+            for i in group {
+                duplicate[i] = true;
             }
         }
+        LevelMenu { selection: 0, pack, duplicate, width: 4, height: 4, progress, bindings }
+    }
+
+    fn per_page(&self) -> i32 {
+        self.width * self.height
+    }
+
+    // the page always follows the selection so whatever is selected is on screen
+    fn page(&self) -> i32 {
+        self.selection / self.per_page()
+    }
+
+    fn num_pages(&self) -> i32 {
+        ((self.pack.levels.len() as i32 + self.per_page() - 1) / self.per_page()).max(1)
+    }
 
-        levels.sort_by_key(|lm| lm.level.complexity());
-        println!("levels len: {}", levels.len());
+    fn layout(&self, screen_rect: Rect) -> (Rect, Rect) {
+        let menu_rect = screen_rect.fit_center_square();
+        (menu_rect.child(0.0, 0.0, 1.0, 0.92), menu_rect.child(0.0, 0.92, 1.0, 0.08))
+    }
 
-        LevelMenu { selection: 0, levels, width: 4 }
+    fn page_dot_rect(&self, pages_rect: Rect, page: i32) -> Rect {
+        pages_rect.grid_child(page, 0, self.num_pages(), 1).fit_center_square().dilate(-0.015)
     }
 
     pub fn handle_command(&mut self, command: MenuCommand) -> SceneOutcome {
         match command {
             MenuCommand::Move(dir) => {
                 let new_selection = self.selection + dir.0 + self.width * dir.1;
-                if new_selection >= 0 && new_selection < self.levels.len() as i32 {
+                if new_selection >= 0 && new_selection < self.pack.levels.len() as i32 {
                     self.selection = new_selection;
                 }
             },
-            MenuCommand::Select => {return SceneOutcome::Pop(SceneSignal::LevelChoice(self.levels[self.selection as usize].level.clone()))},
+            MenuCommand::Page(dp) => {
+                let last = self.pack.levels.len() as i32 - 1;
+                self.selection = (self.selection + dp * self.per_page()).min(last).max(0);
+            },
+            MenuCommand::Highlight(idx) => {
+                if idx >= 0 && idx < self.pack.levels.len() as i32 {
+                    self.selection = idx;
+                }
+            },
+            MenuCommand::Select => {
+                if let Some(level) = self.pack.levels.get(self.selection as usize) {
+                    return SceneOutcome::Pop(SceneSignal::LevelChoice(level.clone()));
+                }
+            },
+            MenuCommand::Play => {
+                let idx = self.selection as usize;
+                if idx < self.pack.levels.len() && self.pack.is_unlocked(idx, &self.progress.borrow()) {
+                    let level = &self.pack.levels[idx];
+                    return SceneOutcome::Push(Box::new(Game::new_timed(level.level.clone(), level.par, self.progress.clone(), self.bindings.clone())));
+                }
+            },
             MenuCommand::Quit => {return SceneOutcome::Pop(SceneSignal::JustPop)},
+            MenuCommand::Help => {return SceneOutcome::Push(Box::new(HelpScreen::new("level menu", &self.bindings.menu)))},
         };
         return SceneOutcome::None;
     }
 }
 
 impl Scene for LevelMenu {
-    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, TriangleBuffer, Option<TriangleBufferUV>) {
-        let mut select = false;
-
-        let outcome = inputs.events.iter().filter_map(|e| match e {
-            KEvent::Keyboard(VirtualKeyCode::W, true) => Some(MenuCommand::Move((0, -1))),
-            KEvent::Keyboard(VirtualKeyCode::S, true) => Some(MenuCommand::Move((0, 1))),
-            KEvent::Keyboard(VirtualKeyCode::A, true) => Some(MenuCommand::Move((-1, 0))),
-            KEvent::Keyboard(VirtualKeyCode::D, true) => Some(MenuCommand::Move((1, 0))),
-            KEvent::Keyboard(VirtualKeyCode::Return, true) => Some(MenuCommand::Select),
-            KEvent::Keyboard(VirtualKeyCode::Escape, true) => Some(MenuCommand::Quit),
-            _ => {None},
-        }).filter_map(|c| match self.handle_command(c) {
-            SceneOutcome::Pop(signal) => Some(SceneOutcome::Pop(signal)),
-            _ => None,
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>) {
+        let mut commands = actions(&self.bindings.menu, &inputs);
+
+        // mouse is hit tested against the page as it was before this frames commands
+        let (grid_rect, pages_rect) = self.layout(inputs.screen_rect);
+        let w = self.width;
+        let h = self.height;
+        let page_start = self.page() * self.per_page();
+        let moved = inputs.events.iter().any(|e| match e {KEvent::MouseMotion(_) => true, _ => false});
+        let click = inputs.lmb_pressed();
+        for i in 0..self.per_page() {
+            let level_idx = page_start + i;
+            if level_idx < self.pack.levels.len() as i32 && grid_rect.grid_child(i % w, i / w, w, h).contains(inputs.mouse_pos) {
+                if moved || click {
+                    commands.push(MenuCommand::Highlight(level_idx));
+                }
+                if click {
+                    commands.push(MenuCommand::Select);
+                }
+            }
+        }
+        let steps = inputs.wheel_steps(grid_rect);
+        if steps != 0 {
+            commands.push(MenuCommand::Page(steps));
+        }
+        for page in 0..self.num_pages() {
+            if click && self.page_dot_rect(pages_rect, page).contains(inputs.mouse_pos) {
+                commands.push(MenuCommand::Highlight(page * self.per_page()));
+            }
+        }
+
+        let outcome = commands.into_iter().filter_map(|c| match self.handle_command(c) {
+            SceneOutcome::None => None,
+            so => Some(so),
         }).nth(0).unwrap_or(SceneOutcome::None);
 
-        let mut buf = TriangleBuffer::new(inputs.screen_rect);
-        let mut buf_uv = TriangleBufferUV::new(inputs.screen_rect, ATLAS_W, ATLAS_H);
+        let mut buf = TriangleBuffer::new();
+        let mut buf_uv = TriangleBufferUV::new(ATLAS_W, ATLAS_H);
 
-        let w = self.width;
-        let h = 4;
+        let page_start = self.page() * self.per_page();
+        let progress = self.progress.borrow();
+
+        for page in 0..self.num_pages() {
+            let colour = if page == self.page() { Vec3::new(1.0, 1.0, 1.0) } else { Vec3::new(0.3, 0.3, 0.3) };
+            buf.draw_rect(self.page_dot_rect(pages_rect, page), colour, 1.0);
+        }
+
+        // pack on the left of the grid, whats selected on the right
+        let menu_rect = inputs.screen_rect.fit_center_square();
+        let side_w = menu_rect.x - inputs.screen_rect.x;
+        let text_style = TextStyle { size: 0.04, align: Align::Centre, wrap: true };
+        let pack_rect = Rect::new(inputs.screen_rect.x, 0.1, side_w, 0.8).dilate(-0.02);
+        let pack_text = format!("{}\n\npage {} of {}\n{} of {} solved", self.pack.meta.name, self.page() + 1, self.num_pages(), self.pack.num_solved(&progress), self.pack.levels.len());
+        buf_uv.draw_text_style(pack_rect, &pack_text, Vec3::new(1.0, 1.0, 1.0), 1.0, text_style);
+        if let Some(level) = self.pack.levels.get(self.selection as usize) {
+            let level_rect = Rect::new(menu_rect.x + menu_rect.w, 0.1, side_w, 0.8).dilate(-0.02);
+            let level_text = if level.author.is_empty() { level.name.clone() } else { format!("{}\n\nby {}", level.name, level.author) };
+            buf_uv.draw_text_style(level_rect, &level_text, Vec3::new(1.0, 1.0, 1.0), 1.0, text_style);
+        }
 
         for j in 0..h {
             for i in 0..w {
-                let level_idx = i + j*w;
-                let level_rect = inputs.screen_rect.fit_center_square().grid_child(i, j, w, h);
+                let level_idx = page_start + i + j*w;
+                let level_rect = grid_rect.grid_child(i, j, w, h);
                 if level_idx == self.selection {
                     buf.draw_rect(level_rect, Vec3::new(1.0, 1.0, 1.0), 1.0);
                 }
-                if let Some(level) = self.levels.get(level_idx as usize) {
+                if let Some(level) = self.pack.levels.get(level_idx as usize) {
                     level.level.frame(&mut buf, &mut buf_uv, level_rect.dilate(-0.01), &inputs, None);
+                    if !self.pack.is_unlocked(level_idx as usize, &progress) {
+                        buf.draw_rect(level_rect.dilate(-0.01), Vec3::new(0.05, 0.05, 0.05), 6.0);
+                    }
+                    let hash = self.pack.hashes[level_idx as usize];
+                    let solved_rect = level_rect.child(0.0, 0.95, 1.0, 0.05).dilate(-0.01);
+                    if progress.is_solved(hash) {
+                        buf.draw_rect(solved_rect, Vec3::new(0.2, 0.8, 0.2), 5.0);
+                    } else {
+                        buf.draw_rect(solved_rect, Vec3::new(0.3, 0.3, 0.3), 5.0);
+                    }
+                    if let Some(medal) = progress.medal(hash, level.par) {
+                        let medal_rect = level_rect.child(0.85, 0.0, 0.15, 0.15).dilate(-0.01);
+                        buf.draw_rect(medal_rect, medal_colour(medal), 5.0);
+                    }
+                    if self.duplicate[level_idx as usize] {
+                        let duplicate_rect = level_rect.child(0.0, 0.0, 0.15, 0.15).dilate(-0.01);
+                        buf.draw_rect(duplicate_rect, Vec3::new(1.0, 0.5, 0.0), 5.0);
+                    }
                 }
             }
         }
 
-        (outcome, buf, Some(buf_uv))
+        (outcome, vec![Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])
     }
     
     fn handle_signal(&mut self, signal: SceneSignal) -> SceneOutcome {
         SceneOutcome::None
     }
-}
\ No newline at end of file
+}
+
+fn medal_colour(medal: Medal) -> Vec3 {
+    match medal {
+        Medal::Gold => Vec3::new(1.0, 0.8, 0.1),
+        Medal::Silver => Vec3::new(0.75, 0.75, 0.8),
+        Medal::Bronze => Vec3::new(0.7, 0.4, 0.15),
+    }
+}
@@ -0,0 +1,197 @@
+use crate::level::*;
+use crate::migrate::*;
+use crate::manifest::*;
+
+use std::fmt;
+
+// Hand editable level files that diff nicely, eg.
+//
+//   version 1
+//   name corner
+//   author someone
+//   rating 3
+//   par 20
+//   size 3 2
+//   palette
+//   0120
+//   1102
+//   board
+//   0120# ----. 1102.
+//   ----. 0120. ----.
+//
+// A cell is its top, right, bottom and left colours then # for locked or . for not, ---- is empty.
+// One board line per row from the top. Blank lines and lines starting with // are skipped.
+
+pub const LEVEL_TEXT_EXTENSION: &str = "tlevel";
+
+#[derive(Debug)]
+pub struct LevelTextError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LevelTextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn tile_text(tile: Tile) -> String {
+    tile.iter().map(|c| char::from(b'0' + *c)).collect()
+}
+
+pub fn level_to_text(metadata: &LevelMetadata) -> String {
+    let level = &metadata.level;
+    let mut out = String::new();
+    out.push_str(&format!("version {}\n", LEVEL_FORMAT_VERSION));
+    out.push_str(&format!("name {}\n", metadata.name));
+    if !metadata.author.is_empty() {
+        out.push_str(&format!("author {}\n", metadata.author));
+    }
+    out.push_str(&format!("rating {}\n", metadata.rating));
+    if let Some(par) = metadata.par {
+        out.push_str(&format!("par {}\n", par));
+    }
+    out.push_str(&format!("size {} {}\n", level.w, level.h));
+    out.push_str("palette\n");
+    for tile in level.tile_palette.iter() {
+        out.push_str(&tile_text(*tile));
+        out.push('\n');
+    }
+    out.push_str("board\n");
+    for y in 0..level.h {
+        let cells: Vec<String> = (0..level.w).map(|x| {
+            let tile = level.get_tile(x, y).map_or(String::from("----"), tile_text);
+            let lock = if level.get_locked(x, y) { '#' } else { '.' };
+            format!("{}{}", tile, lock)
+        }).collect();
+        out.push_str(&cells.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_tile(s: &str) -> Result<Tile, String> {
+    let digits: Vec<u8> = s.bytes().collect();
+    if digits.len() != 4 {
+        return Err(format!("'{}' should be 4 colour digits", s));
+    }
+    let mut tile = [0; 4];
+    for i in 0..4 {
+        match digits[i].checked_sub(b'0') {
+            Some(c) if (c as usize) < COLOURS.len() => tile[i] = c,
+            _ => return Err(format!("'{}' isn't a colour, only 0 to {}", digits[i] as char, COLOURS.len() - 1)),
+        }
+    }
+    Ok(tile)
+}
+
+fn parse_cell(s: &str) -> Result<(Option<Tile>, bool), String> {
+    if s.len() != 5 || !s.is_ascii() {
+        return Err(format!("'{}' should be 4 colour digits and # or .", s));
+    }
+    let (tile, lock) = s.split_at(4);
+    let locked = match lock {
+        "#" => true,
+        "." => false,
+        _ => return Err(format!("'{}' isn't a lock marker, use # or .", lock)),
+    };
+    if tile == "----" {
+        Ok((None, locked))
+    } else {
+        Ok((Some(parse_tile(tile)?), locked))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(key: &str, s: &str) -> Result<T, String> {
+    s.trim().parse().map_err(|_| format!("{} '{}' isn't a number", key, s.trim()))
+}
+
+enum Section {
+    Header,
+    Palette,
+    Board,
+}
+
+pub fn level_from_text(text: &str) -> Result<LevelMetadata, LevelTextError> {
+    // the text format started at version 1 so theres nothing older to migrate
+    let mut version = LEVEL_FORMAT_VERSION;
+    let mut name = String::from("untitled");
+    let mut author = String::new();
+    let mut rating = 0;
+    let mut par = None;
+    let mut level: Option<Level> = None;
+    let mut palette = Vec::new();
+    let mut y = 0;
+    let mut section = Section::Header;
+
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |message: String| LevelTextError { line: line_no, message };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        if line == "palette" {
+            section = Section::Palette;
+            continue;
+        }
+        if line == "board" {
+            if level.is_none() {
+                return Err(err(String::from("board before size")));
+            }
+            section = Section::Board;
+            continue;
+        }
+
+        match section {
+            Section::Header => {
+                let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+                match key {
+                    "version" => version = parse_number(key, value).map_err(err)?,
+                    "name" => name = value.trim().to_string(),
+                    "author" => author = value.trim().to_string(),
+                    "rating" => rating = parse_number(key, value).map_err(err)?,
+                    "par" => par = Some(parse_number(key, value).map_err(err)?),
+                    "size" => {
+                        let dims: Vec<i32> = value.split_whitespace().map(|d| parse_number(key, d)).collect::<Result<_, _>>().map_err(err)?;
+                        match dims.as_slice() {
+                            [w, h] if *w > 0 && *h > 0 => level = Some(Level::new(*w, *h)),
+                            _ => return Err(err(format!("size should be width and height, got '{}'", value))),
+                        }
+                    },
+                    _ => return Err(err(format!("unknown field '{}'", key))),
+                }
+            },
+            Section::Palette => palette.push(parse_tile(line).map_err(err)?),
+            Section::Board => {
+                let level = level.as_mut().unwrap();
+                let cells: Vec<&str> = line.split_whitespace().collect();
+                if y >= level.h {
+                    return Err(err(format!("more than {} board rows", level.h)));
+                }
+                if cells.len() != level.w as usize {
+                    return Err(err(format!("row has {} cells, size says {}", cells.len(), level.w)));
+                }
+                for (x, cell) in cells.iter().enumerate() {
+                    let (tile, locked) = parse_cell(cell).map_err(err)?;
+                    if let Some(tile) = tile {
+                        level.set_tile(x as i32, y, tile);
+                    }
+                    level.set_locked(x as i32, y, locked);
+                }
+                y += 1;
+            },
+        }
+    }
+
+    let at_end = |message: String| LevelTextError { line: text.lines().count(), message };
+    let mut level = level.ok_or_else(|| at_end(String::from("no size")))?;
+    if y != level.h {
+        return Err(at_end(format!("{} board rows, size says {}", y, level.h)));
+    }
+    level.tile_palette = palette;
+    level.validate().map_err(|why| at_end(why.to_string()))?;
+
+    Ok(LevelMetadata { version, level, name, author, rating, par, source: None })
+}
@@ -8,9 +8,28 @@ mod application;
 mod game;
 mod manifest;
 mod level_menu;
+mod pack;
+mod pack_menu;
+mod migrate;
+mod level_code;
+mod level_text;
+mod recovery;
+mod raster;
+mod replay;
+mod cli;
+mod bindings;
 mod kgui;
+mod progress;
+mod daily;
+#[cfg(test)]
+mod golden;
 
 use application::*;
+use cli::*;
+use migrate::*;
+use pack::*;
+use replay::*;
+use recovery::*;
 use glutin::event::{Event, WindowEvent};
 use glutin::event_loop::ControlFlow;
 use std::env;
@@ -21,8 +40,39 @@ use std::env;
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
 
+    let args: Vec<String> = env::args().collect();
+    // solve, validate, generate, render, stats, see cli.rs
+    if let Some(code) = run_command(&args[1..]) {
+        std::process::exit(code);
+    }
+    if args.get(1).map(|a| a.as_str()) == Some("--migrate") {
+        let dir = args.get(2).map(|d| d.as_str()).unwrap_or(LEVELS_DIR);
+        let ok = migrate_dir(dir);
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+    // --replay file [-o last_frame.png], no window
+    if args.get(1).map(|a| a.as_str()) == Some("--replay") {
+        let path = match args.get(2) {
+            Some(path) => path,
+            None => {
+                println!("usage: --replay <file> [-o out.png]");
+                std::process::exit(1);
+            },
+        };
+        let screenshot = if args.get(3).map(|a| a.as_str()) == Some("-o") { args.get(4).map(|s| s.as_str()) } else { None };
+        match replay(path, screenshot) {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            },
+        }
+    }
+    // --record file, plays as normal and writes every frames input to it
+    let record_path = if args.get(1).map(|a| a.as_str()) == Some("--record") { args.get(2).map(|s| s.as_str()) } else { None };
+
     let event_loop = glutin::event_loop::EventLoop::new();
-    let mut application = Application::new(&event_loop);
+    let mut application = Application::new(&event_loop, record_path);
     
     event_loop.run(move |event, _, control_flow| {
         application.handle_event(&event);
@@ -30,6 +80,8 @@ fn main() {
             Event::LoopDestroyed |
             Event::WindowEvent {event: WindowEvent::CloseRequested, ..}
             => {
+                // closing on purpose isnt a crash, dont offer to recover next launch
+                EditorRecovery::remove();
                 *control_flow = ControlFlow::Exit;
             },
             _ => (),
@@ -30,5 +30,9 @@ pub const TILE_CRACKS2: i32 = 34;
 pub const TILE_CRACKS3: i32 = 35;
 pub const CHECKERBOARD: i32 = 36;
 
+// printable ascii from ' ' to '~', one glyph per sprite, white so draw_text can colour them
+pub const FONT_START: i32 = 160;
+
+pub const ATLAS_PATH: &str = "src/atlas.png";
 pub const ATLAS_W: i32 = 16;
 pub const ATLAS_H: i32 = 16;
\ No newline at end of file
@@ -0,0 +1,173 @@
+use crate::pack::*;
+
+use serde_json::Value;
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const LEVEL_FORMAT_VERSION: u32 = 1;
+
+// MIGRATIONS[n] takes a version n level to version n+1, so a file from any version runs the tail of the chain
+const MIGRATIONS: [fn(&mut Value); LEVEL_FORMAT_VERSION as usize] = [
+    v0_to_v1,
+];
+
+// v0 was bare LevelMetadata from before files had a version, v1 added the version and par time
+fn v0_to_v1(value: &mut Value) {
+    if let Some(obj) = value.as_object_mut() {
+        obj.entry("par").or_insert(Value::Null);
+    }
+}
+
+pub fn file_version(value: &Value) -> u32 {
+    value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+// Err is the files version when its newer than this build knows how to read
+pub fn migrate_level(mut value: Value) -> Result<Value, u32> {
+    let version = file_version(&value);
+    if version > LEVEL_FORMAT_VERSION {
+        return Err(version);
+    }
+    for migration in MIGRATIONS[version as usize..].iter() {
+        migration(&mut value);
+    }
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(String::from("version"), Value::from(LEVEL_FORMAT_VERSION));
+    }
+    Ok(value)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let path = entry?.path();
+        let dest = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &dest)?;
+        } else {
+            fs::copy(&path, &dest)?;
+        }
+    }
+    Ok(())
+}
+
+// returns whether the file needed rewriting
+fn migrate_file(path: &Path) -> Result<bool, String> {
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut value: Value = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+
+    let changed = if path.extension().map_or(false, |ext| ext == PACK_EXTENSION) {
+        let mut changed = false;
+        if let Some(levels) = value.get_mut("levels").and_then(|l| l.as_array_mut()) {
+            for level in levels.iter_mut() {
+                if file_version(level) != LEVEL_FORMAT_VERSION {
+                    *level = migrate_level(level.take()).map_err(|v| format!("level version {} is newer than {}", v, LEVEL_FORMAT_VERSION))?;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    } else if file_version(&value) != LEVEL_FORMAT_VERSION {
+        value = migrate_level(value).map_err(|v| format!("version {} is newer than {}", v, LEVEL_FORMAT_VERSION))?;
+        true
+    } else {
+        false
+    };
+
+    if changed {
+        fs::write(path, serde_json::to_string(&value).unwrap()).map_err(|e| e.to_string())?;
+    }
+    Ok(changed)
+}
+
+fn migrate_files(dir: &Path, migrated: &mut i32, failed: &mut i32) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("couldn't read {}: {}", dir.display(), e);
+            *failed += 1;
+            return;
+        },
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            migrate_files(&path, migrated, failed);
+        } else if !path.extension().map_or(false, |ext| ext == LEVEL_EXTENSION || ext == PACK_EXTENSION) {
+            // pack.json, text levels which only exist from version 1, and anything else that isnt ours
+            continue;
+        } else {
+            match migrate_file(&path) {
+                Ok(true) => {
+                    println!("migrated {}", path.display());
+                    *migrated += 1;
+                },
+                Ok(false) => {},
+                Err(e) => {
+                    println!("couldn't migrate {}: {}", path.display(), e);
+                    *failed += 1;
+                },
+            }
+        }
+    }
+}
+
+// Rewrites every level under dir to the current version in place, after copying the whole thing to a backup next to it
+pub fn migrate_dir(dir: &str) -> bool {
+    if !Path::new(dir).is_dir() {
+        println!("{} is not a directory", dir);
+        return false;
+    }
+    // so . and .. have a name to put the backup next to
+    let dir = match fs::canonicalize(dir) {
+        Ok(dir) => dir,
+        Err(e) => {
+            println!("couldn't find {}: {}", dir, e);
+            return false;
+        },
+    };
+    let name = match dir.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => {
+            println!("can't migrate {}, theres nowhere next to it for the backup", dir.display());
+            return false;
+        },
+    };
+
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let backup = dir.with_file_name(format!("{}.bak-{}", name, secs));
+    if let Err(e) = copy_dir(&dir, &backup) {
+        println!("couldn't back up {} to {}: {}, not migrating", dir.display(), backup.display(), e);
+        return false;
+    }
+    println!("backed up {} to {}", dir.display(), backup.display());
+
+    let mut migrated = 0;
+    let mut failed = 0;
+    migrate_files(&dir, &mut migrated, &mut failed);
+    println!("migrated {} files to version {}, {} failed", migrated, LEVEL_FORMAT_VERSION, failed);
+    failed == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn v0_is_upgraded() {
+        let v0 = json!({ "level": {}, "name": "old", "author": "", "rating": 0 });
+        let value = migrate_level(v0).unwrap();
+        assert_eq!(file_version(&value), LEVEL_FORMAT_VERSION);
+        assert_eq!(value.get("par"), Some(&Value::Null));
+        assert_eq!(value.get("name"), Some(&json!("old")));
+    }
+
+    #[test]
+    fn too_new_is_refused() {
+        let future = json!({ "version": LEVEL_FORMAT_VERSION + 1, "name": "new" });
+        assert_eq!(migrate_level(future), Err(LEVEL_FORMAT_VERSION + 1));
+    }
+}
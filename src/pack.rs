@@ -0,0 +1,258 @@
+use crate::level::*;
+use crate::progress::*;
+use crate::migrate::*;
+use crate::level_text::*;
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use std::fmt;
+use std::fs::*;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const LEVELS_DIR: &str = "levels/";
+pub const LEVEL_EXTENSION: &str = "level";
+pub const PACK_METADATA_FILE: &str = "pack.json";
+pub const PACK_EXTENSION: &str = "pack";
+
+fn default_true() -> bool { true }
+
+// Either pack.json inside a pack directory, or the header of a single .pack file
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PackMetadata {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    // campaign packs open one level at a time, each solve unlocks the next
+    #[serde(default = "default_true")]
+    pub sequential: bool,
+    // file names in play order, anything not listed goes on the end by complexity
+    #[serde(default)]
+    pub order: Vec<String>,
+}
+
+impl PackMetadata {
+    pub fn new(name: String, sequential: bool) -> PackMetadata {
+        PackMetadata { name, author: String::new(), description: String::new(), sequential, order: Vec::new() }
+    }
+}
+
+#[derive(Clone)]
+pub struct LevelPack {
+    pub meta: PackMetadata,
+    pub levels: Vec<LevelMetadata>,
+    // Level::hash of each level, worked out once since the canonical form isn't cheap
+    pub hashes: Vec<u64>,
+}
+
+impl LevelPack {
+    pub fn new(meta: PackMetadata, levels: Vec<LevelMetadata>) -> LevelPack {
+        let hashes = levels.iter().map(|lm| lm.level.hash()).collect();
+        LevelPack { meta, levels, hashes }
+    }
+
+    pub fn is_unlocked(&self, idx: usize, progress: &Progress) -> bool {
+        !self.meta.sequential || idx == 0 || progress.is_solved(self.hashes[idx - 1])
+    }
+
+    pub fn num_solved(&self, progress: &Progress) -> usize {
+        self.hashes.iter().filter(|h| progress.is_solved(**h)).count()
+    }
+
+    // groups of level indices that are the same puzzle up to symmetry and recolouring
+    pub fn duplicates(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(u64, Vec<usize>)> = Vec::new();
+        for (i, hash) in self.hashes.iter().enumerate() {
+            match groups.iter_mut().find(|(h, _)| h == hash) {
+                Some((_, group)) => group.push(i),
+                None => groups.push((*hash, vec![i])),
+            }
+        }
+        // the hash is only 64 bits, so anything sharing one gets split up again by the full canonical form
+        let mut duplicates = Vec::new();
+        for (_, group) in groups.into_iter().filter(|(_, group)| group.len() > 1) {
+            let mut same: Vec<(Vec<u8>, Vec<usize>)> = Vec::new();
+            for i in group {
+                let form = self.levels[i].level.canonical_form();
+                match same.iter_mut().find(|(f, _)| *f == form) {
+                    Some((_, group)) => group.push(i),
+                    None => same.push((form, vec![i])),
+                }
+            }
+            duplicates.extend(same.into_iter().map(|(_, group)| group).filter(|group| group.len() > 1));
+        }
+        duplicates
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelLoadError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_json::Error),
+    TooNew(PathBuf, u32),
+    Text(PathBuf, LevelTextError),
+    Invalid(PathBuf, &'static str),
+}
+
+impl LevelLoadError {
+    pub fn path(&self) -> &Path {
+        match self {
+            LevelLoadError::Io(path, _) => path,
+            LevelLoadError::Parse(path, _) => path,
+            LevelLoadError::TooNew(path, _) => path,
+            LevelLoadError::Text(path, _) => path,
+            LevelLoadError::Invalid(path, _) => path,
+        }
+    }
+}
+
+impl fmt::Display for LevelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelLoadError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            LevelLoadError::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            LevelLoadError::TooNew(path, v) => write!(f, "{}: format version {} is newer than this build ({})", path.display(), v, LEVEL_FORMAT_VERSION),
+            LevelLoadError::Text(path, e) => write!(f, "{}: {}", path.display(), e),
+            LevelLoadError::Invalid(path, why) => write!(f, "{}: {}", path.display(), why),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, LevelLoadError> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| LevelLoadError::Io(path.to_path_buf(), e))?;
+    Ok(contents)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, LevelLoadError> {
+    serde_json::from_str(&read_file(path)?).map_err(|e| LevelLoadError::Parse(path.to_path_buf(), e))
+}
+
+fn read_dir_sorted(path: &Path) -> Result<Vec<PathBuf>, LevelLoadError> {
+    let mut paths = Vec::new();
+    for entry in read_dir(path).map_err(|e| LevelLoadError::Io(path.to_path_buf(), e))? {
+        paths.push(entry.map_err(|e| LevelLoadError::Io(path.to_path_buf(), e))?.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn from_migrated(path: &Path, value: Value) -> Result<LevelMetadata, LevelLoadError> {
+    let value = migrate_level(value).map_err(|v| LevelLoadError::TooNew(path.to_path_buf(), v))?;
+    let metadata: LevelMetadata = serde_json::from_value(value).map_err(|e| LevelLoadError::Parse(path.to_path_buf(), e))?;
+    // parsing only checks the shape of the json, not that the level makes sense
+    metadata.level.validate().map_err(|why| LevelLoadError::Invalid(path.to_path_buf(), why))?;
+    Ok(metadata)
+}
+
+pub fn is_text_level(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == LEVEL_TEXT_EXTENSION)
+}
+
+// older files are upgraded in memory, --migrate is what rewrites them on disk
+pub fn load_level_file(path: &Path) -> Result<LevelMetadata, LevelLoadError> {
+    if is_text_level(path) {
+        let metadata = level_from_text(&read_file(path)?).map_err(|e| LevelLoadError::Text(path.to_path_buf(), e))?;
+        if metadata.version > LEVEL_FORMAT_VERSION {
+            return Err(LevelLoadError::TooNew(path.to_path_buf(), metadata.version));
+        }
+        return Ok(LevelMetadata { source: Some(path.to_path_buf()), ..metadata });
+    }
+    let metadata = from_migrated(path, read_json(path)?)?;
+    Ok(LevelMetadata { source: Some(path.to_path_buf()), ..metadata })
+}
+
+fn is_pack_file(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == PACK_EXTENSION)
+}
+
+// Broken levels are skipped and reported in errors, a broken pack.json or unreadable directory skips the whole pack
+fn load_pack_dir(path: &Path, errors: &mut Vec<LevelLoadError>) -> Result<LevelPack, LevelLoadError> {
+    let meta_path = path.join(PACK_METADATA_FILE);
+    let meta: PackMetadata = if meta_path.exists() {
+        read_json(&meta_path)?
+    } else {
+        PackMetadata::new(path.file_name().unwrap().to_string_lossy().to_string(), true)
+    };
+
+    let mut ordered = Vec::new();
+    let mut unordered = Vec::new();
+    for level_path in read_dir_sorted(path)? {
+        if level_path.is_dir() || level_path.file_name().map_or(false, |f| f == PACK_METADATA_FILE) {
+            continue;
+        }
+        let file_name = level_path.file_name().unwrap().to_string_lossy().to_string();
+        let level = match load_level_file(&level_path) {
+            Ok(level) => level,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            },
+        };
+        match meta.order.iter().position(|f| *f == file_name) {
+            Some(idx) => ordered.push((idx, level)),
+            None => unordered.push(level),
+        }
+    }
+    ordered.sort_by_key(|(idx, _)| *idx);
+    unordered.sort_by_key(|lm| lm.level.complexity());
+
+    Ok(LevelPack::new(meta, ordered.into_iter().map(|(_, lm)| lm).chain(unordered).collect()))
+}
+
+fn load_pack_file(path: &Path) -> Result<LevelPack, LevelLoadError> {
+    let mut value: Value = read_json(path)?;
+    let levels = match value.get_mut("levels").and_then(|l| l.as_array_mut()) {
+        Some(levels) => levels.drain(..).map(|l| from_migrated(path, l)).collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    let meta: PackMetadata = serde_json::from_value(value).map_err(|e| LevelLoadError::Parse(path.to_path_buf(), e))?;
+    Ok(LevelPack::new(meta, levels))
+}
+
+// Subdirectories and .pack files are packs, loose levels at the top go in their own unordered pack.
+// Never fails as a whole, anything that couldn't be loaded comes back in the error list.
+pub fn load_packs(dir: &str) -> (Vec<LevelPack>, Vec<LevelLoadError>) {
+    let mut packs = Vec::new();
+    let mut loose = Vec::new();
+    let mut errors = Vec::new();
+
+    let dir = Path::new(dir);
+    if !dir.exists() {
+        // nothing saved yet, the first save makes it
+        return (packs, errors);
+    }
+    let entries = match read_dir_sorted(dir) {
+        Ok(entries) => entries,
+        Err(e) => return (packs, vec![e]),
+    };
+
+    for path in entries {
+        let result = if path.is_dir() {
+            load_pack_dir(&path, &mut errors).map(|pack| packs.push(pack))
+        } else if is_pack_file(&path) {
+            load_pack_file(&path).map(|pack| packs.push(pack))
+        } else {
+            load_level_file(&path).map(|level| loose.push(level))
+        };
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+
+    if !loose.is_empty() {
+        loose.sort_by_key(|lm| lm.level.complexity());
+        packs.push(LevelPack::new(PackMetadata::new(String::from("unsorted"), false), loose));
+    }
+
+    for e in errors.iter() {
+        println!("couldn't load {}", e);
+    }
+    println!("packs: {}, errors: {}", packs.len(), errors.len());
+    (packs, errors)
+}
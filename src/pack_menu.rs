@@ -0,0 +1,164 @@
+use crate::level::*;
+use crate::application::*;
+use crate::renderer::*;
+use crate::manifest::*;
+use crate::kmath::*;
+use crate::kgui::*;
+use crate::rendererUV::{TriangleBufferUV, TextStyle, Align};
+use crate::game::*;
+use crate::daily::*;
+use crate::progress::*;
+use crate::pack::*;
+use crate::level_menu::*;
+use crate::bindings::*;
+
+
+// selection index of the daily puzzle strip above the pack list
+const DAILY_SELECTION: i32 = -1;
+
+pub struct PackMenu {
+    selection: i32,
+    packs: Vec<LevelPack>,
+    errors: Vec<LevelLoadError>,
+
+    daily_date: String,
+    daily_level: Level,
+    progress: SharedProgress,
+    bindings: SharedBindings,
+}
+
+impl PackMenu {
+    pub fn new(progress: SharedProgress, bindings: SharedBindings) -> PackMenu {
+        let (packs, errors) = load_packs(LEVELS_DIR);
+        let daily_date = today();
+        let daily_level = daily_level(&daily_date);
+
+        PackMenu { selection: 0, packs, errors, daily_date, daily_level, progress, bindings }
+    }
+
+    // daily, pack list, and the errors section which only takes space when something failed to load
+    fn layout(&self, screen_rect: Rect) -> (Rect, Rect, Rect) {
+        let menu_rect = screen_rect.fit_center_square();
+        let errors_h = if self.errors.is_empty() { 0.0 } else { 0.1 };
+        (
+            menu_rect.child(0.0, 0.0, 1.0, 0.2),
+            menu_rect.child(0.0, 0.2, 1.0, 0.8 - errors_h),
+            menu_rect.child(0.0, 1.0 - errors_h, 1.0, errors_h),
+        )
+    }
+
+    fn row_rect(&self, list_rect: Rect, i: i32) -> Rect {
+        list_rect.grid_child(0, i, 1, (self.packs.len() as i32).max(4))
+    }
+
+    pub fn handle_command(&mut self, command: MenuCommand) -> SceneOutcome {
+        match command {
+            MenuCommand::Move((_, dy)) => {
+                let new_selection = self.selection + dy;
+                if new_selection >= DAILY_SELECTION && new_selection < self.packs.len() as i32 {
+                    self.selection = new_selection;
+                }
+            },
+            MenuCommand::Page(_) => {},
+            MenuCommand::Highlight(idx) => {
+                if idx >= DAILY_SELECTION && idx < self.packs.len() as i32 {
+                    self.selection = idx;
+                }
+            },
+            MenuCommand::Select | MenuCommand::Play if self.selection == DAILY_SELECTION => {
+                return SceneOutcome::Push(Box::new(Game::new_daily(self.daily_level.clone(), self.daily_date.clone(), self.progress.clone(), self.bindings.clone())));
+            },
+            MenuCommand::Select | MenuCommand::Play => {
+                if let Some(pack) = self.packs.get(self.selection as usize) {
+                    return SceneOutcome::Push(Box::new(LevelMenu::new(pack.clone(), self.progress.clone(), self.bindings.clone())));
+                }
+            },
+            MenuCommand::Quit => {return SceneOutcome::Pop(SceneSignal::JustPop)},
+            MenuCommand::Help => {return SceneOutcome::Push(Box::new(HelpScreen::new("packs", &self.bindings.menu)))},
+        };
+        SceneOutcome::None
+    }
+}
+
+impl Scene for PackMenu {
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>) {
+        let mut commands = actions(&self.bindings.menu, &inputs);
+
+        let (daily_rect, list_rect, errors_rect) = self.layout(inputs.screen_rect);
+        let moved = inputs.events.iter().any(|e| match e {KEvent::MouseMotion(_) => true, _ => false});
+        let click = inputs.lmb_pressed();
+        let hovered = if daily_rect.contains(inputs.mouse_pos) {
+            Some(DAILY_SELECTION)
+        } else {
+            (0..self.packs.len() as i32).find(|i| self.row_rect(list_rect, *i).contains(inputs.mouse_pos))
+        };
+        if let Some(idx) = hovered {
+            if moved || click {
+                commands.push(MenuCommand::Highlight(idx));
+            }
+            if click {
+                commands.push(MenuCommand::Select);
+            }
+        }
+
+        let steps = inputs.wheel_steps(list_rect);
+        if steps != 0 {
+            commands.push(MenuCommand::Move((0, steps)));
+        }
+
+        let outcome = commands.into_iter().filter_map(|c| match self.handle_command(c) {
+            SceneOutcome::None => None,
+            so => Some(so),
+        }).nth(0).unwrap_or(SceneOutcome::None);
+
+        let mut buf = TriangleBuffer::new();
+        let mut buf_uv = TriangleBufferUV::new(ATLAS_W, ATLAS_H);
+
+        let progress = self.progress.borrow();
+
+        if self.selection == DAILY_SELECTION {
+            buf.draw_rect(daily_rect, Vec3::new(1.0, 1.0, 1.0), 1.0);
+        }
+        if progress.daily.contains_key(&self.daily_date) {
+            buf.draw_rect(daily_rect.dilate(-0.005), Vec3::new(0.1, 0.4, 0.1), 1.5);
+        }
+        self.daily_level.frame(&mut buf, &mut buf_uv, daily_rect.dilate(-0.01), &inputs, None);
+
+        // each row is the first level as a preview and a bar for how much of the pack is solved
+        for (i, pack) in self.packs.iter().enumerate() {
+            let row_rect = self.row_rect(list_rect, i as i32);
+            if i as i32 == self.selection {
+                buf.draw_rect(row_rect, Vec3::new(1.0, 1.0, 1.0), 1.0);
+            }
+            let preview_rect = row_rect.child(0.0, 0.0, 0.5, 1.0).dilate(-0.01);
+            if let Some(first) = pack.levels.first() {
+                first.level.frame(&mut buf, &mut buf_uv, preview_rect, &inputs, None);
+            }
+
+            let name_rect = row_rect.child(0.55, 0.1, 0.4, 0.25);
+            buf_uv.draw_text(name_rect, &pack.meta.name, Vec3::new(1.0, 1.0, 1.0), 2.0);
+            let bar_rect = row_rect.child(0.55, 0.4, 0.4, 0.2);
+            let solved = pack.num_solved(&progress) as f32 / pack.levels.len().max(1) as f32;
+            buf.draw_rect(bar_rect, Vec3::new(0.3, 0.3, 0.3), 2.0);
+            buf.draw_rect(bar_rect.child(0.0, 0.0, solved, 1.0), Vec3::new(0.2, 0.8, 0.2), 3.0);
+        }
+
+        // one line per file that couldn't be loaded, whatever doesnt fit is still in the console
+        if !self.errors.is_empty() {
+            buf.draw_rect(errors_rect, Vec3::new(0.3, 0.05, 0.05), 1.0);
+            let errors: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+            let style = TextStyle { size: 0.02, align: Align::Left, wrap: true };
+            buf_uv.draw_text_style(errors_rect.dilate(-0.01), &errors.join("\n"), Vec3::new(1.0, 0.6, 0.6), 2.0, style);
+        }
+
+        (outcome, vec![Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])
+    }
+
+    fn handle_signal(&mut self, signal: SceneSignal) -> SceneOutcome {
+        match signal {
+            // a level picked for editing goes on down to the editor
+            SceneSignal::LevelChoice(level) => SceneOutcome::Pop(SceneSignal::LevelChoice(level)),
+            _ => SceneOutcome::None,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::daily::*;
+
+use serde::{Serialize, Deserialize};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+pub const PROGRESS_PATH: &str = "progress.json";
+
+// loaded once by Application and handed down to whichever scenes record or show progress
+pub type SharedProgress = Rc<RefCell<Progress>>;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct DailyRecord {
+    pub seconds: f32,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LevelRecord {
+    #[serde(default)]
+    pub solved: bool,
+    pub best_time: Option<f32>,
+    #[serde(default)]
+    pub best_moves: Option<i32>,
+    #[serde(default)]
+    pub hints_used: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Medal {
+    Gold,
+    Silver,
+    Bronze,
+}
+
+impl Medal {
+    // gold for beating par, silver for getting close, bronze for finishing at all
+    pub fn for_time(seconds: f32, par: Option<f32>) -> Medal {
+        match par {
+            Some(par) if seconds <= par => Medal::Gold,
+            Some(par) if seconds <= par * 1.5 => Medal::Silver,
+            _ => Medal::Bronze,
+        }
+    }
+}
+
+// daily keyed by date string so the file stays readable, levels keyed by Level::hash.
+// Records from before the hash was 64 bits still load but won't match anything.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Progress {
+    pub daily: BTreeMap<String, DailyRecord>,
+    #[serde(default)]
+    pub levels: BTreeMap<u64, LevelRecord>,
+    #[serde(default)]
+    pub best_streak: u32,
+    // where save writes to, None keeps it in memory for tests and replays
+    #[serde(skip)]
+    pub path: Option<String>,
+}
+
+impl Progress {
+    pub fn load() -> Progress {
+        let progress = match std::fs::read_to_string(PROGRESS_PATH) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                println!("couldn't parse {}: {}, starting fresh", PROGRESS_PATH, e);
+                Progress::default()
+            }),
+            Err(_) => Progress::default(),
+        };
+        Progress { path: Some(String::from(PROGRESS_PATH)), ..progress }
+    }
+
+    pub fn load_shared() -> SharedProgress {
+        Rc::new(RefCell::new(Progress::load()))
+    }
+
+    // write to a temp file and rename over the old one so a crash mid-write can't eat the save
+    pub fn save(&self) {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return,
+        };
+        let tmp_path = format!("{}.tmp", path);
+        let str = serde_json::to_string_pretty(self).unwrap();
+        let result = File::create(&tmp_path)
+            .and_then(|mut f| {
+                f.write_all(str.as_bytes())?;
+                f.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&tmp_path, path));
+        if let Err(e) = result {
+            println!("couldn't write {}: {}", path, e);
+        }
+    }
+
+    // keeps the best time, returns true if this was a new best
+    pub fn record_daily(&mut self, date: &str, seconds: f32) -> bool {
+        let new_best = match self.daily.get(date) {
+            Some(record) if record.seconds <= seconds => false,
+            _ => {
+                self.daily.insert(date.to_string(), DailyRecord { seconds });
+                true
+            },
+        };
+        self.best_streak = self.best_streak.max(self.streak(date));
+        new_best
+    }
+
+    // consecutive dailies solved, counting back from date
+    pub fn streak(&self, date: &str) -> u32 {
+        let mut day = match days_from_date(date) {
+            Some(day) => day,
+            None => return 0,
+        };
+        let mut streak = 0;
+        while self.daily.contains_key(&date_from_days(day)) {
+            streak += 1;
+            day -= 1;
+        }
+        streak
+    }
+
+    pub fn record_solve(&mut self, hash: u64, seconds: Option<f32>, moves: i32, hints: i32) {
+        let record = self.levels.entry(hash).or_default();
+        record.solved = true;
+        record.hints_used += hints;
+        if let Some(seconds) = seconds {
+            record.best_time = Some(record.best_time.map_or(seconds, |best| best.min(seconds)));
+        }
+        record.best_moves = Some(record.best_moves.map_or(moves, |best| best.min(moves)));
+    }
+
+    pub fn is_solved(&self, hash: u64) -> bool {
+        // older saves only kept best times
+        self.levels.get(&hash).map_or(false, |r| r.solved || r.best_time.is_some())
+    }
+
+    pub fn medal(&self, hash: u64, par: Option<f32>) -> Option<Medal> {
+        self.levels.get(&hash).and_then(|r| r.best_time).map(|t| Medal::for_time(t, par))
+    }
+}
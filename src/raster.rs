@@ -0,0 +1,139 @@
+use crate::application::*;
+use crate::kimg::*;
+use crate::kmath::*;
+use crate::kgui::*;
+use crate::level::*;
+use crate::manifest::*;
+use crate::renderer::*;
+use crate::rendererUV::*;
+
+pub const THUMBNAILS_DIR: &str = "thumbnails/";
+
+// Does on the CPU what Renderer and RendererUV do with OpenGL, for screenshots and for drawing
+// without a window. Same rules as the GPU path: each layers camera takes positions from screen units
+// to the window and depth to z like the projection uniform does, its clip works like the scissor test,
+// the depth test is LESS and shared by every layer, and its src alpha blending with nearest sampling.
+pub struct Rasterizer {
+    pub image: ImageBufferA,
+    depth: Vec<f32>,
+    atlas: ImageBufferA,
+}
+
+impl Rasterizer {
+    pub fn new(w: usize, h: usize, atlas: ImageBufferA) -> Rasterizer {
+        let mut rasterizer = Rasterizer {
+            image: ImageBufferA::new(w, h),
+            depth: Vec::new(),
+            atlas,
+        };
+        rasterizer.clear();
+        rasterizer
+    }
+
+    // opaque black, which is what the window shows after a clear
+    pub fn clear(&mut self) {
+        self.image.pixels = vec![(0, 0, 0, 255); self.image.w * self.image.h];
+        self.depth = vec![1.0; self.image.w * self.image.h];
+    }
+
+    pub fn draw(&mut self, layer: &Layer) {
+        let clip = layer.clip.unwrap_or(Rect::new(0.0, 0.0, 1.0, 1.0));
+        self.draw_tris(&layer.tris, &layer.camera, clip);
+        if let Some(tri_uvs) = layer.tri_uvs.as_ref() {
+            self.draw_tri_uvs(tri_uvs, &layer.camera, clip);
+        }
+    }
+
+    fn draw_tris(&mut self, buf: &TriangleBuffer, camera: &Camera, clip: Rect) {
+        for tri in buf.tris.iter() {
+            let colour = [tri.a.colour, tri.b.colour, tri.c.colour];
+            let pos = [tri.a.pos, tri.b.pos, tri.c.pos].map(|p| camera.to_viewport(p));
+            raster_tri(&mut self.image, &mut self.depth, pos, clip, |l| {
+                let c = colour[0] * l[0] + colour[1] * l[1] + colour[2] * l[2];
+                [c.x, c.y, c.z, c.w]
+            });
+        }
+    }
+
+    // like uv.frag the texture is multiplied by the vertex colour
+    fn draw_tri_uvs(&mut self, buf: &TriangleBufferUV, camera: &Camera, clip: Rect) {
+        let atlas = &self.atlas;
+        for tri in buf.tris.iter() {
+            let uv = [tri.a.uv, tri.b.uv, tri.c.uv];
+            let colour = [tri.a.colour, tri.b.colour, tri.c.colour];
+            let pos = [tri.a.pos, tri.b.pos, tri.c.pos].map(|p| camera.to_viewport(p));
+            raster_tri(&mut self.image, &mut self.depth, pos, clip, |l| {
+                let [r, g, b, a] = sample(atlas, uv[0] * l[0] + uv[1] * l[1] + uv[2] * l[2]);
+                let c = colour[0] * l[0] + colour[1] * l[1] + colour[2] * l[2];
+                [r * c.x, g * c.y, b * c.z, a]
+            });
+        }
+    }
+}
+
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+// Fills pixels whose centres are inside the triangle, shade gets the barycentric weights.
+// pos is 0..1 across the window with z ready for the depth test, nothing outside clip gets touched
+fn raster_tri(image: &mut ImageBufferA, depth: &mut [f32], pos: [Vec3; 3], clip: Rect, shade: impl Fn([f32; 3]) -> [f32; 4]) {
+    let (w, h) = (image.w as f32, image.h as f32);
+    let p = pos.map(|v| Vec2::new(v.x * w, v.y * h));
+    let z = pos.map(|v| v.z);
+    let area = edge(p[0], p[1], p[2]);
+    if area == 0.0 {
+        return;
+    }
+
+    // rounded the same way the scissor rect is
+    let (clip_left, clip_right) = ((clip.x * w).round().max(0.0), (clip.right() * w).round().min(w));
+    let (clip_top, clip_bot) = ((clip.y * h).round().max(0.0), (clip.bot() * h).round().min(h));
+    let min_x = p.iter().map(|v| v.x).fold(f32::MAX, f32::min).floor().max(clip_left) as usize;
+    let max_x = p.iter().map(|v| v.x).fold(f32::MIN, f32::max).ceil().min(clip_right).max(clip_left) as usize;
+    let min_y = p.iter().map(|v| v.y).fold(f32::MAX, f32::min).floor().max(clip_top) as usize;
+    let max_y = p.iter().map(|v| v.y).fold(f32::MIN, f32::max).ceil().min(clip_bot).max(clip_top) as usize;
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let centre = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let l = [edge(p[1], p[2], centre) / area, edge(p[2], p[0], centre) / area, edge(p[0], p[1], centre) / area];
+            if l.iter().any(|l| *l < 0.0) {
+                continue;
+            }
+            let idx = y * image.w + x;
+            let frag_z = z[0] * l[0] + z[1] * l[1] + z[2] * l[2];
+            if frag_z >= depth[idx] {
+                continue;
+            }
+            // depth is written even for see through pixels, same as GL
+            depth[idx] = frag_z;
+
+            let [r, g, b, a] = shade(l);
+            let dst = image.pixels[idx];
+            let blend = |src: f32, dst: u8| ((src * a + dst as f32 / 255.0 * (1.0 - a)).max(0.0).min(1.0) * 255.0).round() as u8;
+            image.pixels[idx] = (blend(r, dst.0), blend(g, dst.1), blend(b, dst.2), blend(a, dst.3));
+        }
+    }
+}
+
+// The atlas is uploaded with bytes_transpose so u picks the row and v the column. Only right for a square atlas, which it is.
+fn sample(atlas: &ImageBufferA, uv: Vec2) -> [f32; 4] {
+    let x = ((uv.y * atlas.w as f32) as usize).min(atlas.w - 1);
+    let y = ((uv.x * atlas.h as f32) as usize).min(atlas.h - 1);
+    let px = atlas.get_px(x, y);
+    [px.0 as f32 / 255.0, px.1 as f32 / 255.0, px.2 as f32 / 255.0, px.3 as f32 / 255.0]
+}
+
+// the level on its own, drawn the same way the menus draw it
+pub fn level_thumbnail(level: &Level, size: usize, atlas: ImageBufferA) -> ImageBufferA {
+    let rect = Rect::new(0.0, 0.0, 1.0, 1.0);
+    let inputs = FrameInputState::new(rect);
+    let mut buf = TriangleBuffer::new();
+    let mut buf_uv = TriangleBufferUV::new(ATLAS_W, ATLAS_H);
+    level.frame(&mut buf, &mut buf_uv, rect, &inputs, None);
+
+    let mut rasterizer = Rasterizer::new(size, size, atlas);
+    rasterizer.draw(&Layer::screen(rect, buf, Some(buf_uv)));
+    rasterizer.image
+}
@@ -0,0 +1,129 @@
+use crate::level::*;
+use crate::application::*;
+use crate::manifest::*;
+use crate::kmath::*;
+use crate::kgui::*;
+
+use serde::{Serialize, Deserialize};
+use glutin::event::VirtualKeyCode;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+pub const RECOVERY_PATH: &str = "editor.recovery";
+
+// Everything the editor would lose in a crash. Written every few seconds while it changes,
+// removed once the level is saved properly.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EditorRecovery {
+    pub metadata: LevelMetadata,
+    // LevelMetadata skips this when serializing so it goes alongside
+    pub source: Option<PathBuf>,
+    pub place_tile: Tile,
+    pub tile_selection: usize,
+    pub history: Vec<Level>,
+    pub undo_pos: usize,
+}
+
+impl EditorRecovery {
+    pub fn load() -> Option<EditorRecovery> {
+        let str = std::fs::read_to_string(RECOVERY_PATH).ok()?;
+        match serde_json::from_str::<EditorRecovery>(&str) {
+            Ok(recovery) if recovery.undo_pos < recovery.history.len() => Some(recovery),
+            Ok(_) => {
+                println!("{} has a bad undo position, ignoring it", RECOVERY_PATH);
+                None
+            },
+            Err(e) => {
+                println!("couldn't read {}: {}, ignoring it", RECOVERY_PATH, e);
+                None
+            },
+        }
+    }
+
+    // same tmp and rename as progress so a crash mid write doesn't lose the last good one
+    pub fn write_str(str: &str) {
+        let tmp_path = format!("{}.tmp", RECOVERY_PATH);
+        let result = File::create(&tmp_path)
+            .and_then(|mut f| {
+                f.write_all(str.as_bytes())?;
+                f.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&tmp_path, RECOVERY_PATH));
+        if let Err(e) = result {
+            println!("couldn't write {}: {}", RECOVERY_PATH, e);
+        }
+    }
+
+    pub fn remove() {
+        if let Err(e) = std::fs::remove_file(RECOVERY_PATH) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                println!("couldn't remove {}: {}", RECOVERY_PATH, e);
+            }
+        }
+    }
+}
+
+// Shown over the editor on startup when theres a recovery file.
+// Return or Y restores it, Escape or N throws it away.
+pub struct RecoveryPrompt {
+    recovery: Option<EditorRecovery>,
+    // replays discard without deleting the real file
+    pub no_disk: bool,
+}
+
+impl RecoveryPrompt {
+    pub fn new(recovery: EditorRecovery) -> RecoveryPrompt {
+        RecoveryPrompt { recovery: Some(recovery), no_disk: false }
+    }
+
+    fn layout(screen_rect: Rect) -> (Rect, Rect, Rect) {
+        let prompt_rect = screen_rect.fit_center_square().dilate(-0.1);
+        (
+            prompt_rect.child(0.0, 0.0, 1.0, 0.8),
+            prompt_rect.child(0.0, 0.8, 0.5, 0.2).dilate(-0.02),
+            prompt_rect.child(0.5, 0.8, 0.5, 0.2).dilate(-0.02),
+        )
+    }
+}
+
+impl Scene for RecoveryPrompt {
+    fn frame(&mut self, inputs: FrameInputState) -> (SceneOutcome, Vec<Layer>) {
+        let mut gui = Gui::new(&inputs);
+
+        let (preview_rect, restore_rect, discard_rect) = RecoveryPrompt::layout(inputs.screen_rect);
+        gui.depth = 2.0;
+        gui.tooltip(restore_rect, "restore (return)");
+        gui.tooltip(discard_rect, "discard (escape)");
+        let restore = gui.button(restore_rect, ButtonAppearance::Sprite(OPEN))
+            || inputs.key_pressed(VirtualKeyCode::Return) || inputs.key_pressed(VirtualKeyCode::Y);
+        let discard = gui.button(discard_rect, ButtonAppearance::Text("discard"))
+            || inputs.key_pressed(VirtualKeyCode::Escape) || inputs.key_pressed(VirtualKeyCode::N);
+
+        let outcome = if restore {
+            SceneOutcome::Pop(SceneSignal::RestoreEditor(Box::new(self.recovery.take().unwrap())))
+        } else if discard {
+            if !self.no_disk {
+                EditorRecovery::remove();
+            }
+            SceneOutcome::Pop(SceneSignal::JustPop)
+        } else {
+            SceneOutcome::None
+        };
+
+        if let Some(recovery) = self.recovery.as_ref() {
+            recovery.metadata.level.frame(&mut gui.buf, &mut gui.buf_uv, preview_rect, &inputs, None);
+        }
+        // green and red edges so its obvious which is which
+        gui.buf.draw_rect(restore_rect.dilate(0.01), Vec3::new(0.2, 0.6, 0.2), 1.5);
+        gui.buf.draw_rect(discard_rect.dilate(0.01), Vec3::new(0.6, 0.2, 0.2), 1.5);
+
+        let (buf, buf_uv) = gui.finish();
+        (outcome, vec![Layer::screen(inputs.screen_rect, buf, Some(buf_uv))])
+    }
+
+    fn handle_signal(&mut self, _signal: SceneSignal) -> SceneOutcome {
+        SceneOutcome::None
+    }
+}
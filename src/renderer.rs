@@ -2,30 +2,27 @@ use crate::kmath::*;
 use glow::*;
 use std::fmt;
 
+// Triangles in screen units, the camera of the layer theyre presented in says how they go onto the window
+#[derive(Default)]
 pub struct TriangleBuffer {
-    screen_rect: Rect,
     pub tris: Vec<Triangle3>,
 }
 
 impl TriangleBuffer {
-    pub fn new(screen_rect: Rect) -> TriangleBuffer {
-        TriangleBuffer { screen_rect, tris: Vec::new() }
+    pub fn new() -> TriangleBuffer {
+        TriangleBuffer { tris: Vec::new() }
     }
 
-    fn push_triangle(&mut self, mut tri: Triangle3) {
-        tri.a.pos.x  = (tri.a.pos.x - self.screen_rect.x) / self.screen_rect.w;
-        tri.a.pos.y  = (tri.a.pos.y - self.screen_rect.y) / self.screen_rect.h;
-
-        tri.b.pos.x  = (tri.b.pos.x - self.screen_rect.x) / self.screen_rect.w;
-        tri.b.pos.y  = (tri.b.pos.y - self.screen_rect.y) / self.screen_rect.h;
-
-        tri.c.pos.x  = (tri.c.pos.x - self.screen_rect.x) / self.screen_rect.w;
-        tri.c.pos.y  = (tri.c.pos.y - self.screen_rect.y) / self.screen_rect.h;
-
+    fn push_triangle(&mut self, tri: Triangle3) {
         self.tris.push(tri);
     }
 
     pub fn draw_rect(&mut self, r: Rect, colour: Vec3, depth: f32) {
+        self.draw_rect_alpha(r, colour.with_alpha(1.0), depth);
+    }
+
+    // see through, whats underneath has to be drawn first or the depth test throws it away
+    pub fn draw_rect_alpha(&mut self, r: Rect, colour: Vec4, depth: f32) {
         let v1 = Vert3 {
             pos: Vec3::new(r.x, r.y, depth),
             colour,
@@ -46,7 +43,19 @@ impl TriangleBuffer {
         self.push_triangle(Triangle3{ a: v1, b: v3, c: v2 });
     }
 
+    // a frame t thick just inside r
+    pub fn draw_outline(&mut self, r: Rect, t: f32, colour: Vec3, depth: f32) {
+        self.draw_rect(Rect::new(r.x, r.y, r.w, t), colour, depth);
+        self.draw_rect(Rect::new(r.x, r.y + r.h - t, r.w, t), colour, depth);
+        self.draw_rect(Rect::new(r.x, r.y + t, t, r.h - 2.0 * t), colour, depth);
+        self.draw_rect(Rect::new(r.x + r.w - t, r.y + t, t, r.h - 2.0 * t), colour, depth);
+    }
+
     pub fn draw_tri(&mut self, tri: Triangle, colour: Vec3, depth: f32) {
+        self.draw_tri_alpha(tri, colour.with_alpha(1.0), depth);
+    }
+
+    pub fn draw_tri_alpha(&mut self, tri: Triangle, colour: Vec4, depth: f32) {
         let a = Vert3 {
             pos: Vec3::new(tri.a.x, tri.a.y, depth),
             colour,
@@ -67,14 +76,14 @@ impl TriangleBuffer {
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Triangle3 {
-    a: Vert3,
-    b: Vert3,
-    c: Vert3,
+    pub a: Vert3,
+    pub b: Vert3,
+    pub c: Vert3,
 }
 
 impl fmt::Debug for Triangle3 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "pos: ({},{},{}), ({},{},{}), ({},{},{}) colour: ({},{},{})", 
+        write!(f, "pos: ({},{},{}), ({},{},{}), ({},{},{}) colour: ({},{},{},{})", 
             self.a.pos.x,
             self.a.pos.y,
             self.a.pos.z,
@@ -87,21 +96,23 @@ impl fmt::Debug for Triangle3 {
             self.a.colour.x,
             self.a.colour.y,
             self.a.colour.z,
+            self.a.colour.w,
          )
     }
 }
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
-struct Vert3 {
-    pos: Vec3,
-    colour: Vec3,
+pub struct Vert3 {
+    pub pos: Vec3,
+    pub colour: Vec4,
 }
 
 pub struct Renderer {
     vbo: NativeBuffer,
     vao: NativeVertexArray,
     shader: NativeProgram,
+    projection: Option<NativeUniformLocation>,
 }
 
 impl Renderer {
@@ -116,26 +127,28 @@ impl Renderer {
             let vao = gl.create_vertex_array().unwrap();
             gl.bind_vertex_array(Some(vao));
             
-            gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, 4*2*3, 0);
+            gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, 4*7, 0);
             gl.enable_vertex_attrib_array(0);
-            gl.vertex_attrib_pointer_f32(1, 3, glow::FLOAT, false, 4*2*3, 4*3);
+            gl.vertex_attrib_pointer_f32(1, 4, glow::FLOAT, false, 4*7, 4*3);
             gl.enable_vertex_attrib_array(1);
     
             Renderer {
                 vao,
                 vbo,
                 shader,
+                projection: gl.get_uniform_location(shader, "projection"),
             }
         }
     }
 
-    pub fn present(&mut self, gl: &glow::Context, triangles: TriangleBuffer) {
+    pub fn present(&mut self, gl: &glow::Context, triangles: TriangleBuffer, camera: &Camera) {
         unsafe {
             gl.use_program(Some(self.shader));
+            gl.uniform_matrix_4_f32_slice(self.projection.as_ref(), false, &camera.projection());
             let gpu_bytes: &[u8] = core::slice::from_raw_parts(
                 triangles.tris.as_ptr() as *const u8,
-                3 * 4 * 6 * triangles.tris.len(),
-            ); // 3 for points in triangle, 4 for bytes in float, 6 for floats in vertex
+                3 * 4 * 7 * triangles.tris.len(),
+            ); // 3 for points in triangle, 4 for bytes in float, 7 for floats in vertex
             gl.bind_vertex_array(Some(self.vao));
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
             gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, gpu_bytes, glow::DYNAMIC_DRAW);
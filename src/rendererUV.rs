@@ -1,5 +1,6 @@
 use crate::kmath::*;
 use crate::kimg::*;
+use crate::manifest::*;
 
 use glow::*;
 
@@ -7,38 +8,74 @@ use std::fmt;
 
 #[derive(Debug)]
 pub struct TriangleBufferUV {
-    screen_rect: Rect,
     pub tris: Vec<Triangle3UV>,
     atlas_w: i32,
     atlas_h: i32,
 }
 
 impl TriangleBufferUV {
-    pub fn new(screen_rect: Rect, atlas_w: i32, atlas_h: i32) -> TriangleBufferUV {
-        TriangleBufferUV { screen_rect, tris: Vec::new(), atlas_w, atlas_h }
+    pub fn new(atlas_w: i32, atlas_h: i32) -> TriangleBufferUV {
+        TriangleBufferUV { tris: Vec::new(), atlas_w, atlas_h }
     }
 
-    fn push_triangle(&mut self, mut tri: Triangle3UV) {
-        tri.a.pos.x  = (tri.a.pos.x - self.screen_rect.x) / self.screen_rect.w;
-        tri.a.pos.y  = (tri.a.pos.y - self.screen_rect.y) / self.screen_rect.h;
-
-        tri.b.pos.x  = (tri.b.pos.x - self.screen_rect.x) / self.screen_rect.w;
-        tri.b.pos.y  = (tri.b.pos.y - self.screen_rect.y) / self.screen_rect.h;
-
-        tri.c.pos.x  = (tri.c.pos.x - self.screen_rect.x) / self.screen_rect.w;
-        tri.c.pos.y  = (tri.c.pos.y - self.screen_rect.y) / self.screen_rect.h;
-
-        self.tris.push(tri);
+    pub fn draw_sprite(&mut self, r: Rect, i: i32, depth: f32) {
+        let uv = self.sprite_uv(i);
+        self.draw_uv_rect(r, uv, Vec3::new(1.0, 1.0, 1.0), depth);
     }
 
-    pub fn draw_sprite(&mut self, r: Rect, i: i32, depth: f32) {
-        let colour = Vec3::new(1.0, 1.0, 1.0);
+    fn sprite_uv(&self, i: i32) -> Rect {
         let x = i / self.atlas_w;
         let y = i % self.atlas_h;
         let uv_w = 1.0 / self.atlas_w as f32;
         let uv_h = 1.0 / self.atlas_h as f32;
-        let uv = Rect::new(x as f32 * uv_w, y as f32 * uv_h, uv_w, uv_h);
+        Rect::new(x as f32 * uv_w, y as f32 * uv_h, uv_w, uv_h)
+    }
+
+    // One line, centred, as big as fits in r. Thats what labels and buttons want
+    pub fn draw_text(&mut self, r: Rect, text: &str, colour: Vec3, depth: f32) {
+        let longest = text.lines().map(|l| l.chars().count()).max().unwrap_or(0).max(1);
+        let lines = text.lines().count().max(1);
+        let size = (r.h / lines as f32).min(r.w / (longest as f32 * GLYPH_ASPECT));
+        self.draw_text_style(r, text, colour, depth, TextStyle { size, align: Align::Centre, wrap: false });
+    }
+
+    // Lines go down from the top of r, or sit in the middle of it when centred. Anything past the bottom is cut off
+    pub fn draw_text_style(&mut self, r: Rect, text: &str, colour: Vec3, depth: f32, style: TextStyle) {
+        let glyph_w = style.size * GLYPH_ASPECT;
+        let lines = layout_lines(text, if style.wrap { (r.w / glyph_w).floor() as usize } else { usize::MAX });
+        let total_h = lines.len() as f32 * style.size;
+        let mut y = if style.align == Align::Centre && total_h < r.h { r.y + (r.h - total_h) / 2.0 } else { r.y };
+
+        for line in lines {
+            if y + style.size > r.y + r.h + 0.0001 {
+                break;
+            }
+            let line_w = line.len() as f32 * glyph_w;
+            let mut x = match style.align {
+                Align::Left => r.x,
+                Align::Centre => r.x + (r.w - line_w) / 2.0,
+                Align::Right => r.x + r.w - line_w,
+            };
+            for c in line {
+                if c != ' ' {
+                    let uv = self.glyph_uv(c);
+                    self.draw_uv_rect(Rect::new(x, y, glyph_w, style.size), uv, colour, depth);
+                }
+                x += glyph_w;
+            }
+            y += style.size;
+        }
+    }
+
+    // Glyphs are 5x7 in an 8x8 grid starting one column in, the 6x8 around them includes the gap to the next one
+    fn glyph_uv(&self, c: char) -> Rect {
+        let c = if (' '..='~').contains(&c) { c } else { '?' };
+        let uv = self.sprite_uv(FONT_START + (c as i32 - ' ' as i32));
+        Rect::new(uv.x, uv.y + uv.h / 8.0, uv.w, uv.h * 6.0 / 8.0)
+    }
 
+    // uv is laid out like sprite_uv gives it, uv.x runs down the screen and uv.y across
+    fn draw_uv_rect(&mut self, r: Rect, uv: Rect, colour: Vec3, depth: f32) {
         let v1 = Vert3UV {
             pos: Vec3::new(r.x, r.y, depth),
             colour: colour,
@@ -59,18 +96,71 @@ impl TriangleBufferUV {
             colour: colour,
             uv: uv.bl(),
         };
-        self.push_triangle(Triangle3UV{ a: v1, b: v4, c: v3 });
-        self.push_triangle(Triangle3UV{ a: v1, b: v3, c: v2 });
+        self.tris.push(Triangle3UV{ a: v1, b: v4, c: v3 });
+        self.tris.push(Triangle3UV{ a: v1, b: v3, c: v2 });
     }
 }
 
 
+// glyph width over height
+pub const GLYPH_ASPECT: f32 = 0.75;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Align {
+    Left,
+    Centre,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    // line height in screen units, glyphs are GLYPH_ASPECT times as wide
+    pub size: f32,
+    pub align: Align,
+    // wrap at spaces to the width of the rect, long words get split
+    pub wrap: bool,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> TextStyle {
+        TextStyle { size, align: Align::Left, wrap: true }
+    }
+}
+
+// Splits on newlines and then wraps to width characters
+pub fn layout_lines(text: &str, width: usize) -> Vec<Vec<char>> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line: Vec<char> = Vec::new();
+        for word in paragraph.split(' ') {
+            let mut word: Vec<char> = word.chars().collect();
+            if !line.is_empty() && line.len() + 1 + word.len() > width {
+                lines.push(line);
+                line = Vec::new();
+            } else if !line.is_empty() {
+                line.push(' ');
+            }
+            while line.len() + word.len() > width {
+                let rest = word.split_off(width - line.len());
+                line.append(&mut word);
+                lines.push(line);
+                line = Vec::new();
+                word = rest;
+            }
+            line.append(&mut word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Triangle3UV {
-    a: Vert3UV,
-    b: Vert3UV,
-    c: Vert3UV,
+    pub a: Vert3UV,
+    pub b: Vert3UV,
+    pub c: Vert3UV,
 }
 
 impl fmt::Debug for Triangle3UV {
@@ -94,16 +184,17 @@ impl fmt::Debug for Triangle3UV {
 
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
-struct Vert3UV {
-    pos: Vec3,
-    colour: Vec3,
-    uv: Vec2,
+pub struct Vert3UV {
+    pub pos: Vec3,
+    pub colour: Vec3,
+    pub uv: Vec2,
 }
 
 pub struct RendererUV {
     vbo: NativeBuffer,
     vao: NativeVertexArray,
     shader: NativeProgram,
+    projection: Option<NativeUniformLocation>,
 
     atlas: NativeTexture,
 
@@ -142,14 +233,16 @@ impl RendererUV {
                 vao,
                 vbo,
                 shader,
+                projection: gl.get_uniform_location(shader, "projection"),
                 atlas: texture,
             }
         }
     }
 
-    pub fn present(&mut self, gl: &glow::Context, triangles: TriangleBufferUV) {
+    pub fn present(&mut self, gl: &glow::Context, triangles: TriangleBufferUV, camera: &Camera) {
         unsafe {
             gl.use_program(Some(self.shader));
+            gl.uniform_matrix_4_f32_slice(self.projection.as_ref(), false, &camera.projection());
             gl.bind_texture(glow::TEXTURE_2D, Some(self.atlas));
 
             let gpu_bytes: &[u8] = core::slice::from_raw_parts(
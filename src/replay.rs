@@ -0,0 +1,117 @@
+use crate::application::*;
+use crate::editor::*;
+use crate::kgui::*;
+use crate::kimg::*;
+use crate::kmath::*;
+use crate::manifest::*;
+use crate::progress::*;
+use crate::raster::*;
+use crate::recovery::*;
+use crate::bindings::*;
+
+use serde::{Serialize, Deserialize};
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::rc::Rc;
+
+// A replay is JSON lines: a header with what the game started from, then one FrameInputState per frame
+// exactly as the scenes got it, dt included, so playing it back goes the same way.
+// Whatever is in levels/ and the date for the daily come from the machine replaying it.
+
+pub const REPLAY_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub xres: f32,
+    pub yres: f32,
+    pub progress: Progress,
+    // the restore prompt was up at the start
+    pub recovery: Option<EditorRecovery>,
+    // whatever keys the recording machine had, recordings from before bindings get the defaults
+    #[serde(default = "Bindings::defaults")]
+    pub bindings: Bindings,
+}
+
+pub struct Recorder {
+    file: File,
+    path: String,
+}
+
+impl Recorder {
+    pub fn new(path: &str, header: &ReplayHeader) -> std::io::Result<Recorder> {
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", serde_json::to_string(header).unwrap())?;
+        println!("recording to {}", path);
+        Ok(Recorder { file, path: path.to_string() })
+    }
+
+    // written as it goes so a crash still leaves everything up to it
+    pub fn record(&mut self, inputs: &FrameInputState) {
+        if let Err(e) = writeln!(self.file, "{}", serde_json::to_string(inputs).unwrap()) {
+            println!("couldn't write to {}: {}", self.path, e);
+        }
+    }
+}
+
+// The same scene stack Application::new makes, from the header instead of the files on disk
+pub fn replay_stack(header: &ReplayHeader) -> SceneStack {
+    let progress = Rc::new(RefCell::new(Progress { path: None, ..header.progress.clone() }));
+    let mut editor = Editor::new(progress, Rc::new(header.bindings.clone()));
+    editor.no_disk = true;
+    let mut stack = SceneStack::new(Box::new(editor));
+    if let Some(recovery) = header.recovery.as_ref() {
+        let mut prompt = RecoveryPrompt::new(recovery.clone());
+        prompt.no_disk = true;
+        stack.scenes.push(Box::new(prompt));
+    }
+    stack
+}
+
+// Runs the whole thing without a window, the last frame can be drawn out to a png
+pub fn replay(path: &str, screenshot: Option<&str>) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("couldn't open {}: {}", path, e))?;
+    let mut lines = BufReader::new(file).lines().enumerate();
+
+    let header: ReplayHeader = match lines.next() {
+        Some((_, Ok(line))) => serde_json::from_str(&line).map_err(|e| format!("{}: bad header: {}", path, e))?,
+        Some((_, Err(e))) => return Err(format!("couldn't read {}: {}", path, e)),
+        None => return Err(format!("{} is empty", path)),
+    };
+    if header.version > REPLAY_VERSION {
+        return Err(format!("{} is replay version {}, this build reads up to {}", path, header.version, REPLAY_VERSION));
+    }
+
+    let mut stack = replay_stack(&header);
+    let mut last = None;
+    // the window may have been resized since the header, the last frame has the shape it ended up
+    let mut screen_rect = Rect::new(0.0, 0.0, header.xres / header.yres, 1.0);
+    let mut frames = 0;
+    for (i, line) in lines {
+        let line = line.map_err(|e| format!("couldn't read {}: {}", path, e))?;
+        let inputs: FrameInputState = match serde_json::from_str(&line) {
+            Ok(inputs) => inputs,
+            // the recording got cut off mid line, play what there is
+            Err(e) => {
+                println!("{} line {}: {}, stopping there", path, i + 1, e);
+                break;
+            },
+        };
+        screen_rect = inputs.screen_rect;
+        last = Some(stack.frame(inputs));
+        frames += 1;
+    }
+    println!("replayed {} frames from {}", frames, path);
+
+    if let (Some(out), Some(layers)) = (screenshot, last) {
+        let mut rasterizer = Rasterizer::new((screen_rect.w * header.yres).round() as usize, header.yres as usize, ImageBufferA::new_from_file(ATLAS_PATH));
+        for layer in layers.iter() {
+            rasterizer.draw(layer);
+        }
+        rasterizer.image.dump_to_file(out);
+        println!("saved {}", out);
+    }
+    Ok(())
+}
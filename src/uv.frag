@@ -8,6 +8,7 @@ uniform sampler2D atlas;
 void main() {
     // frag_colour = vec4(1.0, 0.0, 0.0, 1.0);
     // frag_colour = vec4(uv.x, 0.0, uv.y, 1.0);
-    frag_colour = texture(atlas, uv);
+    // sprites are drawn with white, text is tinted
+    frag_colour = texture(atlas, uv) * vec4(vert_colour, 1.0);
     // frag_colour = vec4(vert_colour, 1.0);
 }
\ No newline at end of file